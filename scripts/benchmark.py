@@ -0,0 +1,67 @@
+#!/usr/bin/python3
+"""Measures how fast clog reads a large log file.
+
+Usage: scripts/benchmark.py [size in MB, default 2048] [extra clog arguments]
+
+The file is generated once into target/benchmark-<size>MB.log and reused.
+"""
+import json
+import os
+import subprocess
+import sys
+import time
+
+size_mb = int(sys.argv[1]) if len(sys.argv) > 1 else 2048
+extra_args = sys.argv[2:]
+path = f"target/benchmark-{size_mb}MB.log"
+
+
+def entries():
+    i = 0
+    while True:
+        i += 1
+        seconds = 1648838992 + i // 100
+        yield json.dumps(
+            {
+                "timestamp": {"seconds": seconds, "nanos": i % 100 * 10_000_000},
+                "severity": ["INFO", "DEBUG", "WARNING", "ERROR"][i % 4],
+                "message": f"Handled request {i} for /api/v1/items in {i % 997} ms",
+                "context": {"requestId": f"{i * 2654435761 % 2**32:08x}"},
+                "logger": "api.requests",
+                "http": {"method": "GET", "status": 200, "bytes": i % 4096},
+            }
+        )
+        if i % 10 == 0:
+            yield json.dumps(
+                {
+                    "time": "2022-04-01T18:49:52.123Z",
+                    "level": 50,
+                    "msg": "Query failed\n\twith \"timeout\" after 30 s",
+                    "spans": [{"name": "request", "id": i}],
+                },
+                indent=2,
+            )
+
+
+if not os.path.exists(path):
+    print(f"Generating {path}")
+    with open(path + ".tmp", "w") as f:
+        written = 0
+        for entry in entries():
+            f.write(entry + "\n")
+            written += len(entry) + 1
+            if written >= size_mb * 1024 * 1024:
+                break
+    os.rename(path + ".tmp", path)
+
+subprocess.check_call(["cargo", "build", "--release", "--quiet"])
+
+for args in [[], ["-v"], ["--stats"], ["--parallel"]]:
+    args = args + extra_args
+    start = time.monotonic()
+    subprocess.check_call(
+        ["target/release/clog", "--no-pager", "--color=always", *args, path],
+        stdout=subprocess.DEVNULL,
+    )
+    elapsed = time.monotonic() - start
+    print(f"clog {' '.join(args):<20} {elapsed:6.2f} s {size_mb / elapsed:8.1f} MB/s")
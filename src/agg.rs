@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use chrono::Duration;
+
+use crate::histogram::Buckets;
+use crate::parser::JsonValue;
+use crate::table::{write_columns, Align};
+use crate::{Parser, ParserOutput, PrintConfig, Report};
+
+/// Aggregates of a numeric field, optionally grouped by another field and by
+/// time, for `clog agg`.
+pub struct Aggregation {
+    field: String,
+    by: Option<String>,
+    buckets: Option<Buckets>,
+    /// The field values per time bucket and group.
+    values: BTreeMap<(Option<i64>, String), Vec<f64>>,
+}
+
+impl Aggregation {
+    pub fn new(
+        field: String,
+        by: Option<String>,
+        bucket: Option<Duration>,
+        config: &PrintConfig,
+    ) -> Self {
+        Aggregation {
+            field,
+            by,
+            buckets: bucket.map(|size| Buckets::new(size, config)),
+            values: BTreeMap::new(),
+        }
+    }
+}
+
+impl Report for Aggregation {
+    fn add(&mut self, output: &ParserOutput) {
+        let line = match output {
+            ParserOutput::Log(line) => line,
+            _ => return,
+        };
+        let value = match line.lookup(&self.field) {
+            Some(JsonValue::Num(value)) => value.as_f64(),
+            _ => return,
+        };
+        let group = match &self.by {
+            Some(by) => line.value(by).unwrap_or_else(|| "-".to_string()),
+            None => String::new(),
+        };
+        let bucket = self.buckets.map(|buckets| buckets.index(&line.time));
+        self.values.entry((bucket, group)).or_default().push(value);
+    }
+
+    fn print(
+        &self,
+        f: &mut dyn Write,
+        _config: &PrintConfig,
+        _parser: &Parser,
+    ) -> std::io::Result<()> {
+        let mut header = vec![];
+        let mut align = vec![];
+        if self.buckets.is_some() {
+            header.push("Time");
+            align.push(Align::Left);
+        }
+        if let Some(by) = &self.by {
+            header.push(by.as_str());
+            align.push(Align::Left);
+        }
+        header.extend(["count", "min", "max", "mean", "p50", "p90", "p99"]);
+        align.extend([Align::Right; 7]);
+
+        let rows: Vec<_> = self
+            .values
+            .iter()
+            .map(|((bucket, group), values)| {
+                let mut sorted = values.clone();
+                sorted.sort_by(|a, b| a.total_cmp(b));
+                let mean = sorted.iter().sum::<f64>() / sorted.len() as f64;
+
+                let mut row = vec![];
+                if let (Some(index), Some(buckets)) = (bucket, self.buckets) {
+                    row.push(buckets.label(*index));
+                }
+                if self.by.is_some() {
+                    row.push(group.clone());
+                }
+                row.push(sorted.len().to_string());
+                row.push(format_number(sorted[0]));
+                row.push(format_number(sorted[sorted.len() - 1]));
+                row.push(format_number(mean));
+                for p in [50.0, 90.0, 99.0] {
+                    row.push(format_number(percentile(&sorted, p)));
+                }
+                row
+            })
+            .collect();
+
+        if rows.is_empty() {
+            writeln!(f, "No numeric values found for `{}`", self.field)?;
+            return Ok(());
+        }
+        write_columns(f, &header, &align, &rows)
+    }
+}
+
+/// The nearest-rank percentile of the non-empty, sorted `values`.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 {
+        format!("{value}")
+    } else {
+        format!("{value:.2}")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::histogram::parse_duration;
+    use crate::print_report;
+    use pretty_assertions::assert_eq;
+
+    fn aggregate(by: Option<&str>, bucket: Option<&str>) -> String {
+        colored::control::set_override(false);
+        let input = r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z", "route": "/a", "duration_ms": 10}
+{"message": "a", "timestamp": "2022-04-01T18:49:53Z", "route": "/a", "duration_ms": 30}
+{"message": "a", "timestamp": "2022-04-01T18:50:52Z", "route": "/b", "duration_ms": 1.5}
+{"message": "a", "timestamp": "2022-04-01T18:50:53Z", "route": "/a", "duration_ms": 20}
+{"message": "a", "timestamp": "2022-04-01T18:50:54Z", "route": "/a", "duration_ms": "slow"}
+{"message": "a", "timestamp": "2022-04-01T18:50:55Z", "duration_ms": 100}"#;
+        let config = PrintConfig::default();
+        let bucket = bucket.map(|b| parse_duration(b).unwrap());
+        let mut agg = Aggregation::new(
+            "duration_ms".to_string(),
+            by.map(String::from),
+            bucket,
+            &config,
+        );
+        print_report(&mut agg, input, &config)
+    }
+
+    #[test]
+    fn aggregates_numeric_field() {
+        let expected = r#"count   min  max   mean  p50  p90  p99
+    5  1.50  100  32.30   20  100  100
+"#;
+        assert_eq!(expected, aggregate(None, None));
+    }
+
+    #[test]
+    fn groups_by_field_and_time() {
+        let expected = r#"Time                 route  count   min   max  mean   p50   p90   p99
+2022-04-01 18:49:00  /a         2    10    30    20    10    30    30
+2022-04-01 18:50:00  -          1   100   100   100   100   100   100
+2022-04-01 18:50:00  /a         1    20    20    20    20    20    20
+2022-04-01 18:50:00  /b         1  1.50  1.50  1.50  1.50  1.50  1.50
+"#;
+        assert_eq!(expected, aggregate(Some("route"), Some("1m")));
+    }
+}
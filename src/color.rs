@@ -0,0 +1,421 @@
+use std::env;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use clap::ValueEnum as ClapValueEnum;
+use eyre::eyre;
+
+#[derive(ClapValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Never,
+    Always,
+}
+
+/// Whether to emit colors. `--color always|never` wins over the environment.
+/// With `auto`, `NO_COLOR` turns colors off and `CLICOLOR_FORCE` turns them on
+/// even when the output isn't a terminal.
+fn should_colorize(
+    choice: ColorChoice,
+    no_color: Option<String>,
+    clicolor_force: Option<String>,
+    is_terminal: bool,
+) -> bool {
+    let is_set = |value: Option<String>| value.is_some_and(|v| !v.is_empty() && v != "0");
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto if is_set(no_color) => false,
+        ColorChoice::Auto if is_set(clicolor_force) => true,
+        ColorChoice::Auto => is_terminal,
+    }
+}
+
+/// How many colors the terminal can show.
+#[derive(ClapValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ColorDepth {
+    #[value(name = "truecolor")]
+    TrueColor,
+    #[value(name = "256")]
+    Ansi256,
+    #[value(name = "16")]
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Detects the color depth from `$COLORTERM` and `$TERM`. Unknown
+    /// terminals, like most CI log viewers, get 16 colors.
+    fn detect(colorterm: Option<String>, term: Option<String>) -> Self {
+        let term = term.unwrap_or_default();
+        match colorterm.as_deref() {
+            Some("truecolor") | Some("24bit") => ColorDepth::TrueColor,
+            _ if term.ends_with("-direct") => ColorDepth::TrueColor,
+            _ if term.contains("256color") => ColorDepth::Ansi256,
+            _ => ColorDepth::Ansi16,
+        }
+    }
+}
+
+static DEPTH: AtomicU8 = AtomicU8::new(ColorDepth::TrueColor as u8);
+
+fn depth() -> ColorDepth {
+    match DEPTH.load(Ordering::Relaxed) {
+        0 => ColorDepth::TrueColor,
+        1 => ColorDepth::Ansi256,
+        _ => ColorDepth::Ansi16,
+    }
+}
+
+/// Decides once whether output is colored, and with how many colors. Named
+/// colors are written through `colored`, and 24-bit colors through [Painted].
+pub fn init(choice: ColorChoice, color_depth: Option<ColorDepth>, is_terminal: bool) {
+    let enabled = should_colorize(
+        choice,
+        env::var("NO_COLOR").ok(),
+        env::var("CLICOLOR_FORCE").ok(),
+        is_terminal,
+    );
+    colored::control::set_override(enabled);
+    let color_depth = color_depth
+        .unwrap_or_else(|| ColorDepth::detect(env::var("COLORTERM").ok(), env::var("TERM").ok()));
+    DEPTH.store(color_depth as u8, Ordering::Relaxed);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+/// The default xterm colors of the 16 color palette.
+const PALETTE_16: [Rgb; 16] = [
+    Rgb(0, 0, 0),
+    Rgb(205, 0, 0),
+    Rgb(0, 205, 0),
+    Rgb(205, 205, 0),
+    Rgb(0, 0, 238),
+    Rgb(205, 0, 205),
+    Rgb(0, 205, 205),
+    Rgb(229, 229, 229),
+    Rgb(127, 127, 127),
+    Rgb(255, 0, 0),
+    Rgb(0, 255, 0),
+    Rgb(255, 255, 0),
+    Rgb(92, 92, 255),
+    Rgb(255, 0, 255),
+    Rgb(0, 255, 255),
+    Rgb(255, 255, 255),
+];
+
+/// Palette entries that are readable on both dark and light backgrounds, for
+/// hashed colors on 16 color terminals.
+const ID_COLORS_16: [u8; 11] = [1, 2, 3, 5, 6, 9, 10, 11, 12, 13, 14];
+
+/// The levels of each component in the 6x6x6 cube of the 256 color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl Rgb {
+    fn distance(&self, other: &Rgb) -> i32 {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(self.0, other.0) + d(self.1, other.1) + d(self.2, other.2)
+    }
+
+    fn is_too_dark(&self) -> bool {
+        let luma = 0.2126 * (self.0 as f64) + 0.7152 * (self.1 as f64) + 0.0722 * (self.2 as f64); // per ITU-R BT.709
+        luma < 80.0
+    }
+
+    /// The closest entry in the 256 color palette, from the color cube or the
+    /// gray ramp.
+    fn ansi256(&self) -> u8 {
+        let level = |c: u8| {
+            (0..CUBE_LEVELS.len())
+                .min_by_key(|i| (c as i32 - CUBE_LEVELS[*i] as i32).abs())
+                .unwrap()
+        };
+        let (r, g, b) = (level(self.0), level(self.1), level(self.2));
+        let cube = Rgb(CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+
+        let average = (self.0 as i32 + self.1 as i32 + self.2 as i32) / 3;
+        let gray_step = ((average - 8).max(0) / 10).min(23);
+        let gray_level = (8 + 10 * gray_step) as u8;
+        let gray = Rgb(gray_level, gray_level, gray_level);
+
+        if self.distance(&gray) < self.distance(&cube) {
+            232 + gray_step as u8
+        } else {
+            (16 + 36 * r + 6 * g + b) as u8
+        }
+    }
+
+    /// The closest entry in the 16 color palette.
+    fn ansi16(&self) -> usize {
+        (0..PALETTE_16.len())
+            .min_by_key(|i| self.distance(&PALETTE_16[*i]))
+            .unwrap()
+    }
+
+    /// The SGR parameters to use this as the foreground (`base` 30) or the
+    /// background (`base` 40).
+    fn sgr(&self, base: u8, depth: ColorDepth) -> String {
+        match depth {
+            ColorDepth::TrueColor => format!("{};2;{};{};{}", base + 8, self.0, self.1, self.2),
+            ColorDepth::Ansi256 => format!("{};5;{}", base + 8, self.ansi256()),
+            ColorDepth::Ansi16 => Ink::Named(self.ansi16() as u8).sgr(base, depth),
+        }
+    }
+}
+
+/// A color from the 16 color palette, which terminal themes may redefine, or a
+/// 24-bit color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ink {
+    Named(u8),
+    Rgb(Rgb),
+}
+
+const NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+pub const RED: Ink = Ink::Named(1);
+pub const GREEN: Ink = Ink::Named(2);
+pub const YELLOW: Ink = Ink::Named(3);
+pub const BLUE: Ink = Ink::Named(4);
+pub const MAGENTA: Ink = Ink::Named(5);
+pub const CYAN: Ink = Ink::Named(6);
+pub const WHITE: Ink = Ink::Named(7);
+pub const BRIGHT_BLACK: Ink = Ink::Named(8);
+pub const BRIGHT_RED: Ink = Ink::Named(9);
+pub const BRIGHT_GREEN: Ink = Ink::Named(10);
+pub const BRIGHT_YELLOW: Ink = Ink::Named(11);
+pub const BRIGHT_MAGENTA: Ink = Ink::Named(13);
+pub const BRIGHT_CYAN: Ink = Ink::Named(14);
+pub const BRIGHT_WHITE: Ink = Ink::Named(15);
+
+impl Ink {
+    fn sgr(&self, base: u8, depth: ColorDepth) -> String {
+        match self {
+            Ink::Named(i @ 0..=7) => format!("{}", base + i),
+            Ink::Named(i) => format!("{}", base + 60 + i - 8),
+            Ink::Rgb(rgb) => rgb.sgr(base, depth),
+        }
+    }
+}
+
+impl FromStr for Ink {
+    type Err = eyre::Error;
+
+    /// Parses names like `red` or `bright-red`, and `#rrggbb`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if let Some(hex) = s.strip_prefix('#') {
+            let component = |i: usize| {
+                hex.get(i..i + 2)
+                    .and_then(|c| u8::from_str_radix(c, 16).ok())
+            };
+            return match (hex.len(), component(0), component(2), component(4)) {
+                (6, Some(r), Some(g), Some(b)) => Ok(Ink::Rgb(Rgb(r, g, b))),
+                _ => Err(eyre!("Invalid color `{}`, expected #rrggbb", s)),
+            };
+        }
+        let (name, offset) = match s.strip_prefix("bright-") {
+            Some(name) => (name, 8),
+            None => (s.as_str(), 0),
+        };
+        match (name, NAMES.iter().position(|n| *n == name)) {
+            ("gray" | "grey", _) => Ok(BRIGHT_BLACK),
+            (_, Some(i)) => Ok(Ink::Named(i as u8 + offset)),
+            _ => Err(eyre!("Unknown color `{}`", s)),
+        }
+    }
+}
+
+/// How to print one kind of element, eg. timestamps.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct Style {
+    pub fg: Option<Ink>,
+    pub bold: bool,
+}
+
+impl Style {
+    pub const fn fg(ink: Ink) -> Self {
+        Style {
+            fg: Some(ink),
+            bold: false,
+        }
+    }
+
+    pub const fn bold(ink: Ink) -> Self {
+        Style {
+            fg: Some(ink),
+            bold: true,
+        }
+    }
+
+    pub fn paint<T: ToString>(&self, text: T) -> Painted {
+        Painted {
+            text: text.to_string(),
+            fg: self.fg,
+            bg: None,
+            bold: self.bold,
+        }
+    }
+}
+
+impl FromStr for Style {
+    type Err = eyre::Error;
+
+    /// Parses whitespace separated words like `bold bright-red`. `default`
+    /// keeps the terminal's own foreground color.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut style = Style::default();
+        for word in s.split_whitespace() {
+            match word {
+                "bold" => style.bold = true,
+                "default" => style.fg = None,
+                color => style.fg = Some(color.parse()?),
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// Text with colors, written with the closest colors the terminal can show.
+/// Formatting widths pad the text inside the colors.
+pub struct Painted {
+    text: String,
+    fg: Option<Ink>,
+    bg: Option<Ink>,
+    bold: bool,
+}
+
+impl Painted {
+    fn codes(&self, depth: ColorDepth) -> String {
+        let mut codes = vec![];
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if let Some(fg) = &self.fg {
+            codes.push(fg.sgr(30, depth));
+        }
+        if let Some(bg) = &self.bg {
+            codes.push(bg.sgr(40, depth));
+        }
+        codes.join(";")
+    }
+}
+
+impl fmt::Display for Painted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let codes = self.codes(depth());
+        if codes.is_empty() || !colored::control::SHOULD_COLORIZE.should_colorize() {
+            return f.pad(&self.text);
+        }
+        write!(f, "\x1b[{}m", codes)?;
+        f.pad(&self.text)?;
+        write!(f, "\x1b[0m")
+    }
+}
+
+/// Colors `id` with a color picked from its hash, so that lines with the same
+/// id stand out together. With 16 colors, the hash picks from the readable
+/// palette entries instead, so that ids don't all collapse to a few colors.
+pub fn id_color(id: &str) -> Painted {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    let hash_value = hasher.finish();
+    let bold = ((hash_value >> 24) & 0x1) == 0;
+
+    let (fg, bg) = if depth() == ColorDepth::Ansi16 {
+        let index = ID_COLORS_16[(hash_value % ID_COLORS_16.len() as u64) as usize];
+        (Ink::Named(index), None)
+    } else {
+        let red = (hash_value & 0xFF) as u8;
+        let green = ((hash_value >> 8) & 0xFF) as u8;
+        let blue = ((hash_value >> 16) & 0xFF) as u8;
+        let fg = Rgb(red, green, blue);
+        let bg = if fg.is_too_dark() {
+            Rgb(255, 255, 255)
+        } else {
+            Rgb(0, 0, 0)
+        };
+        (Ink::Rgb(fg), Some(Ink::Rgb(bg)))
+    };
+    Painted {
+        text: id.to_string(),
+        fg: Some(fg),
+        bg,
+        bold,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn honors_choice_and_environment() {
+        let some = |s: &str| Some(s.to_string());
+        assert!(should_colorize(ColorChoice::Auto, None, None, true));
+        assert!(!should_colorize(ColorChoice::Auto, None, None, false));
+        assert!(!should_colorize(ColorChoice::Auto, some("1"), None, true));
+        assert!(should_colorize(ColorChoice::Auto, some(""), None, true));
+        assert!(should_colorize(ColorChoice::Auto, None, some("1"), false));
+        assert!(!should_colorize(ColorChoice::Auto, None, some("0"), false));
+        assert!(should_colorize(ColorChoice::Always, some("1"), None, false));
+        assert!(!should_colorize(ColorChoice::Never, None, some("1"), true));
+
+        colored::control::set_override(false);
+        assert_eq!("abc     ", format!("{:<8}", id_color("abc")));
+    }
+
+    #[test]
+    fn detects_color_depth() {
+        let some = |s: &str| Some(s.to_string());
+        let detect = ColorDepth::detect;
+        assert_eq!(
+            ColorDepth::TrueColor,
+            detect(some("truecolor"), some("xterm"))
+        );
+        assert_eq!(ColorDepth::TrueColor, detect(None, some("xterm-direct")));
+        assert_eq!(ColorDepth::Ansi256, detect(None, some("screen-256color")));
+        assert_eq!(ColorDepth::Ansi16, detect(None, some("xterm")));
+        assert_eq!(ColorDepth::Ansi16, detect(None, None));
+    }
+
+    #[test]
+    fn maps_to_closest_palette_colors() {
+        assert_eq!(196, Rgb(255, 0, 0).ansi256());
+        assert_eq!(16, Rgb(0, 0, 0).ansi256());
+        assert_eq!(244, Rgb(128, 130, 129).ansi256());
+        assert_eq!(9, Rgb(250, 10, 10).ansi16());
+
+        let painted = Painted {
+            text: "id".to_string(),
+            fg: Some(Ink::Rgb(Rgb(250, 10, 10))),
+            bg: Some(Ink::Named(0)),
+            bold: true,
+        };
+        assert_eq!("1;38;2;250;10;10;40", painted.codes(ColorDepth::TrueColor));
+        assert_eq!("1;38;5;196;40", painted.codes(ColorDepth::Ansi256));
+        assert_eq!("1;91;40", painted.codes(ColorDepth::Ansi16));
+    }
+
+    #[test]
+    fn parses_styles() {
+        assert_eq!(
+            Ok(Style::bold(BRIGHT_RED)),
+            "bold bright-red"
+                .parse()
+                .map_err(|e: eyre::Error| e.to_string())
+        );
+        assert_eq!(
+            Style::fg(Ink::Rgb(Rgb(0, 158, 115))),
+            "#009E73".parse().unwrap()
+        );
+        assert_eq!(Style::fg(BRIGHT_BLACK), "grey".parse().unwrap());
+        assert_eq!(Style::default(), "default".parse().unwrap());
+        assert!("#12345".parse::<Style>().is_err());
+        assert!("purple".parse::<Style>().is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use std::mem::take;
+use std::time::{Duration, Instant};
+
+use clap::ValueEnum as ClapValueEnum;
+
+use crate::template::template;
+use crate::{LogLine, ParserOutput, Severity};
+
+/// How long a run of duplicates is held back after its last entry before it is
+/// printed, when no different entry arrives to end it.
+pub const DEDUP_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(ClapValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum DedupMode {
+    /// Collapse entries with identical messages
+    Exact,
+    /// Collapse entries whose messages are identical after masking numbers and ids
+    Masked,
+}
+
+struct Run {
+    key: (String, Severity),
+    line: LogLine,
+    count: usize,
+    last_seen: Instant,
+}
+
+/// Collapses runs of consecutive entries with the same message and severity
+/// into the first entry of the run, annotated with the number of repeats.
+pub struct Dedup {
+    mode: DedupMode,
+    pending: Option<Run>,
+}
+
+impl Dedup {
+    pub fn new(mode: DedupMode) -> Self {
+        Dedup {
+            mode,
+            pending: None,
+        }
+    }
+
+    /// Returns the outputs that are ready to be printed.
+    pub fn push(&mut self, output: ParserOutput) -> Vec<ParserOutput> {
+        let line = match output {
+            ParserOutput::Log(line) => line,
+            ParserOutput::None => return vec![],
+            text => return self.flush().into_iter().chain(Some(text)).collect(),
+        };
+
+        let message = line.raw_message().map(|m| m.1).unwrap_or_default();
+        let message = match self.mode {
+            DedupMode::Exact => message,
+            DedupMode::Masked => template(&message),
+        };
+        let key = (message, line.severity());
+
+        if let Some(run) = &mut self.pending {
+            if run.key == key {
+                run.count += 1;
+                run.last_seen = Instant::now();
+                let first = run.line.time;
+                run.line.repeated = Some((run.count, line.time - first));
+                return vec![];
+            }
+        }
+        let flushed = self.flush();
+        self.pending = Some(Run {
+            key,
+            line,
+            count: 1,
+            last_seen: Instant::now(),
+        });
+        flushed.into_iter().collect()
+    }
+
+    /// When the pending run should be printed even if no other entry arrives.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.pending
+            .as_ref()
+            .map(|run| run.last_seen + DEDUP_TIMEOUT)
+    }
+
+    pub fn flush(&mut self) -> Option<ParserOutput> {
+        take(&mut self.pending).map(|run| ParserOutput::Log(run.line))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Parser, PrintConfig};
+    use pretty_assertions::assert_eq;
+
+    fn dedup(input: &str, mode: DedupMode) -> String {
+        colored::control::set_override(false);
+        let mut dedup = Dedup::new(mode);
+        let mut outputs = vec![];
+        for output in Parser::new().push_lines(input) {
+            outputs.extend(dedup.push(output));
+        }
+        outputs.extend(dedup.flush());
+
+        let mut buffer = Vec::new();
+        for output in outputs {
+            output.print(&mut buffer, &PrintConfig::default()).unwrap();
+        }
+        String::from_utf8(buffer).unwrap()
+    }
+
+    const INPUT: &str = r#"{"message": "Retrying in 5s", "timestamp": "2022-04-01T18:49:52Z", "severity": "WARNING"}
+{"message": "Retrying in 5s", "timestamp": "2022-04-01T18:49:53Z", "severity": "WARNING"}
+{"message": "Retrying in 4s", "timestamp": "2022-04-01T18:49:55.200Z", "severity": "WARNING"}
+{"message": "Retrying in 4s", "timestamp": "2022-04-01T18:49:56Z", "severity": "ERROR"}
+Some text
+{"message": "Retrying in 4s", "timestamp": "2022-04-01T18:49:57Z", "severity": "ERROR"}"#;
+
+    #[test]
+    fn collapses_exact_duplicates() {
+        assert_eq!(
+            dedup(INPUT, DedupMode::Exact),
+            r#"2022-04-01 18:49:52.000Z WARNING Retrying in 5s (×2 over 1.0s)
+2022-04-01 18:49:55.200Z WARNING Retrying in 4s
+2022-04-01 18:49:56.000Z ERROR   Retrying in 4s
+Some text
+2022-04-01 18:49:57.000Z ERROR   Retrying in 4s
+"#
+        );
+    }
+
+    #[test]
+    fn collapses_masked_duplicates() {
+        assert_eq!(
+            dedup(INPUT, DedupMode::Masked),
+            r#"2022-04-01 18:49:52.000Z WARNING Retrying in 5s (×3 over 3.2s)
+2022-04-01 18:49:56.000Z ERROR   Retrying in 4s
+Some text
+2022-04-01 18:49:57.000Z ERROR   Retrying in 4s
+"#
+        );
+    }
+
+    #[test]
+    fn extends_deadline_on_duplicates() {
+        let mut parser = Parser::new();
+        let mut dedup = Dedup::new(DedupMode::Exact);
+        let mut lines = INPUT.lines();
+        for output in parser.push(&format!("{}\n", lines.next().unwrap())) {
+            dedup.push(output);
+        }
+        let first = dedup.deadline().unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        for output in parser.push(&format!("{}\n", lines.next().unwrap())) {
+            assert_eq!(0, dedup.push(output).len());
+        }
+        assert!(dedup.deadline().unwrap() > first);
+    }
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+
+use crate::parser::{JsonValue, RawObject};
+
+/// The fields of a record. `attrs` is only written with the `labels`, `env` or
+/// `tag` log options, as an object of strings.
+const KEYS: [&str; 4] = ["log", "stream", "time", "attrs"];
+
+/// A record written by Docker's json-file log driver, like
+/// `{"log":"...\n","stream":"stdout","time":"..."}`, where `log` is the line
+/// the container wrote. Lines longer than 16 KiB are split over several
+/// records, and only the last one ends with a line break.
+#[derive(Debug, PartialEq)]
+pub struct Record {
+    pub log: String,
+    pub stream: String,
+    pub time: Option<DateTime<Utc>>,
+    /// The labels, environment variables and tag of the container that the
+    /// log options ask for.
+    pub attrs: Vec<(String, String)>,
+}
+
+impl Record {
+    /// Reads `object` as a record, if it is one. Objects with other fields than
+    /// the ones Docker writes are entries that happen to use the same keys.
+    pub fn read(object: &RawObject) -> Option<Record> {
+        if !object.raw_keys().all(|key| KEYS.contains(&key)) {
+            return None;
+        }
+        let string = |key| Some(object.get(key)?.str_value().ok()?.to_string());
+        let log = string("log")?;
+        let time = string("time")?;
+        let attrs = match object.get("attrs") {
+            Some(JsonValue::Object(attrs)) => attrs
+                .iter()
+                .map(|(key, value)| Some((key.to_string(), value.str_value().ok()?.to_string())))
+                .collect::<Option<_>>()?,
+            Some(_) => return None,
+            None => vec![],
+        };
+        Some(Record {
+            log,
+            stream: string("stream")?,
+            time: DateTime::parse_from_rfc3339(&time)
+                .ok()
+                .map(|time| time.with_timezone(&Utc)),
+            attrs,
+        })
+    }
+
+    /// Tells the containers apart, as far as their attrs do, so that the
+    /// parts of long lines of several containers aren't joined together.
+    pub fn source(&self) -> String {
+        let attrs: Vec<_> = self
+            .attrs
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        format!("docker {}", attrs.join(" "))
+    }
+}
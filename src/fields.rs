@@ -0,0 +1,201 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+
+use crate::parser::{JsonValue, Map};
+use crate::table::{write_columns, Align};
+use crate::theme::theme;
+use crate::{format_value, Parser, ParserOutput, PrintConfig, Report};
+
+/// Distinct values are counted exactly up to this limit.
+const MAX_DISTINCT: usize = 1000;
+const MAX_EXAMPLES: usize = 3;
+const MAX_EXAMPLE_LENGTH: usize = 30;
+
+#[derive(Default)]
+struct Field {
+    /// Number of entries containing the field.
+    entries: usize,
+    /// The last entry the field was seen in, so that values inside arrays
+    /// are only counted once per entry.
+    last_entry: usize,
+    types: BTreeSet<&'static str>,
+    distinct: HashSet<u64>,
+    /// Whether a value was left out of `distinct` because it was full.
+    overflowed: bool,
+    examples: Vec<String>,
+}
+
+/// Every dotted key path seen in the input, for `clog fields`.
+#[derive(Default)]
+pub struct FieldStats {
+    entries: usize,
+    fields: BTreeMap<String, Field>,
+}
+
+impl FieldStats {
+    fn visit_map(&mut self, prefix: &str, map: &Map) {
+        for (key, value) in map {
+            let path = if prefix.is_empty() {
+                key.to_string()
+            } else {
+                format!("{prefix}.{key}")
+            };
+            self.visit(path, value);
+        }
+    }
+
+    fn visit(&mut self, path: String, value: &JsonValue) {
+        let entry = self.entries;
+        let field = self.fields.entry(path.clone()).or_default();
+        if field.last_entry != entry {
+            field.last_entry = entry;
+            field.entries += 1;
+        }
+        field.types.insert(value.type_name());
+
+        match value {
+            JsonValue::Object(map) => self.visit_map(&path, map),
+            JsonValue::Array(values) => {
+                let path = format!("{path}[]");
+                for value in values {
+                    self.visit(path.clone(), value);
+                }
+            }
+            _ => {
+                let formatted = format_value(value, true).unwrap_or_else(|| "null".to_string());
+                let mut hasher = DefaultHasher::new();
+                formatted.hash(&mut hasher);
+                let hash = hasher.finish();
+                if field.distinct.len() < MAX_DISTINCT {
+                    field.distinct.insert(hash);
+                } else if !field.distinct.contains(&hash) {
+                    field.overflowed = true;
+                }
+                let example = truncate(&formatted);
+                if field.examples.len() < MAX_EXAMPLES && !field.examples.contains(&example) {
+                    field.examples.push(example);
+                }
+            }
+        }
+    }
+}
+
+impl Report for FieldStats {
+    fn add(&mut self, output: &ParserOutput) {
+        if let ParserOutput::Log(line) = output {
+            self.entries += 1;
+            self.visit_map("", &line.fields());
+        }
+    }
+
+    fn print(
+        &self,
+        f: &mut dyn Write,
+        _config: &PrintConfig,
+        _parser: &Parser,
+    ) -> std::io::Result<()> {
+        let rows: Vec<_> = self
+            .fields
+            .iter()
+            .map(|(path, field)| {
+                let types: Vec<_> = field.types.iter().copied().collect();
+                let present = 100.0 * field.entries as f64 / self.entries.max(1) as f64;
+                let distinct = match field.distinct.len() {
+                    0 => "-".to_string(),
+                    _ if field.overflowed => format!(">{MAX_DISTINCT}"),
+                    n => n.to_string(),
+                };
+                let examples = field.examples.join(", ");
+                let examples = if examples.is_empty() {
+                    examples
+                } else {
+                    theme().dim.paint(examples).to_string()
+                };
+                vec![
+                    path.clone(),
+                    types.join("|"),
+                    format!("{present:.1}%"),
+                    distinct,
+                    examples,
+                ]
+            })
+            .collect();
+
+        write_columns(
+            f,
+            &["Field", "Types", "Present", "Distinct", "Examples"],
+            &[
+                Align::Left,
+                Align::Left,
+                Align::Right,
+                Align::Right,
+                Align::Left,
+            ],
+            &rows,
+        )
+    }
+}
+
+fn truncate(value: &str) -> String {
+    let value = value.replace('\n', " ");
+    if value.chars().count() > MAX_EXAMPLE_LENGTH {
+        let truncated: String = value.chars().take(MAX_EXAMPLE_LENGTH - 3).collect();
+        format!("{truncated}...")
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::print_report;
+    use pretty_assertions::assert_eq;
+
+    fn fields(input: &str) -> String {
+        colored::control::set_override(false);
+        let mut fields = FieldStats::default();
+        print_report(&mut fields, input, &PrintConfig::default())
+    }
+
+    #[test]
+    fn lists_nested_fields() {
+        let input = r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z", "ctx": {"id": 1, "tags": [{"k": "x"}, {"k": "y"}]}}
+{"message": "b", "timestamp": "2022-04-01T18:49:53Z", "ctx": {"id": "two"}}
+not json
+{"message": "a", "timestamp": "2022-04-01T18:49:54Z", "ctx": null}"#;
+        let expected = r#"Field         Types          Present  Distinct  Examples
+ctx           null|object     100.0%         1  null
+ctx.id        number|string    66.7%         2  1, two
+ctx.tags      array            33.3%         -
+ctx.tags[]    object           33.3%         -
+ctx.tags[].k  string           33.3%         2  x, y
+message       string          100.0%         2  a, b
+timestamp     string          100.0%         3  2022-04-01T18:49:52Z, 2022-04-01T18:49:53Z, 2022-04-01T18:49:54Z
+"#;
+        assert_eq!(expected, fields(input));
+    }
+
+    #[test]
+    fn counts_distinct_values_up_to_the_limit() {
+        let ids = |values: &[usize]| {
+            let ids: Vec<_> = values.iter().map(|id| id.to_string()).collect();
+            format!(
+                r#"{{"message": "a", "timestamp": "2022-04-01T18:49:52Z", "ids": [{}]}}"#,
+                ids.join(", ")
+            )
+        };
+        let distinct = |output: String| {
+            let row = output.lines().find(|row| row.starts_with("ids[]"))?;
+            row.split_whitespace().nth(3).map(String::from)
+        };
+
+        let full: Vec<_> = (0..MAX_DISTINCT).collect();
+        let repeated = format!("{}\n{}", ids(&full), ids(&[0]));
+        assert_eq!(Some("1000".to_string()), distinct(fields(&repeated)));
+        let over = format!("{}\n{}", ids(&full), ids(&[MAX_DISTINCT]));
+        assert_eq!(Some(">1000".to_string()), distinct(fields(&over)));
+    }
+}
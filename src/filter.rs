@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use eyre::eyre;
+
+use crate::LogLine;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Exists,
+}
+
+/// Operators in the order they are tried, so that `>=` is found before `>`.
+const OPS: [(&str, Op); 7] = [
+    ("!=", Op::Ne),
+    (">=", Op::Ge),
+    ("<=", Op::Le),
+    ("=", Op::Eq),
+    ("~", Op::Contains),
+    (">", Op::Gt),
+    ("<", Op::Lt),
+];
+
+#[derive(Debug, PartialEq, Clone)]
+struct Condition {
+    key: String,
+    op: Op,
+    value: String,
+}
+
+impl Condition {
+    fn matches(&self, line: &LogLine) -> bool {
+        let value = line.value(&self.key);
+        match (self.op, value) {
+            (Op::Exists, value) => value.is_some(),
+            (Op::Ne, None) => true,
+            (_, None) => false,
+            (Op::Eq, Some(value)) => value == self.value,
+            (Op::Ne, Some(value)) => value != self.value,
+            (Op::Contains, Some(value)) => {
+                value.to_lowercase().contains(&self.value.to_lowercase())
+            }
+            (op, Some(value)) => match (value.parse::<f64>(), self.value.parse::<f64>()) {
+                (Ok(a), Ok(b)) => match op {
+                    Op::Lt => a < b,
+                    Op::Le => a <= b,
+                    Op::Gt => a > b,
+                    _ => a >= b,
+                },
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Conditions on the fields of an entry, separated by whitespace, that must
+/// all hold. Eg. `status>=500 route~/api user.id!=42 trace_id`, where a bare
+/// key means that the field must be present.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct FieldFilter {
+    conditions: Vec<Condition>,
+}
+
+impl FieldFilter {
+    pub fn matches(&self, line: &LogLine) -> bool {
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(line))
+    }
+}
+
+impl FromStr for FieldFilter {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut conditions = vec![];
+        for term in s.split_whitespace() {
+            let found = OPS
+                .iter()
+                .filter_map(|(token, op)| term.find(token).map(|i| (i, *token, *op)))
+                .min_by_key(|(i, _, _)| *i);
+            let condition = match found {
+                Some((0, _, _)) => return Err(eyre!("Missing field name in `{}`", term)),
+                Some((i, token, op)) => Condition {
+                    key: term[..i].to_string(),
+                    op,
+                    value: term[i + token.len()..].to_string(),
+                },
+                None => Condition {
+                    key: term.to_string(),
+                    op: Op::Exists,
+                    value: String::new(),
+                },
+            };
+            conditions.push(condition);
+        }
+        Ok(FieldFilter { conditions })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Parser;
+    use crate::ParserOutput;
+
+    fn line(input: &str) -> LogLine {
+        match Parser::new().push(input).remove(0) {
+            ParserOutput::Log(line) => line,
+            output => panic!("Not a log line: {:?}", output),
+        }
+    }
+
+    #[test]
+    fn filters_on_fields() {
+        let entry = line(
+            r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z", "status": 503, "http": {"route": "/api/wells"}}"#,
+        );
+        let matches = |filter: &str| filter.parse::<FieldFilter>().unwrap().matches(&entry);
+
+        assert!(matches(""));
+        assert!(matches("status=503"));
+        assert!(matches("status>=500 http.route~/API"));
+        assert!(matches("status!=200 missing!=1"));
+        assert!(matches("http.route"));
+        assert!(!matches("status<500"));
+        assert!(!matches("status>=500 http.route=/api"));
+        assert!(!matches("missing"));
+        assert!(!matches("http.route>1"));
+    }
+
+    #[test]
+    fn rejects_missing_field_name() {
+        assert!("=5".parse::<FieldFilter>().is_err());
+    }
+}
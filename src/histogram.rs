@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use chrono::{DateTime, Duration, FixedOffset, TimeZone, Utc};
+use colored::Colorize;
+
+use crate::theme::theme;
+use crate::{Parser, ParserOutput, PrintConfig, Report, Severity};
+
+/// Bucket sizes to choose from when none is given.
+const AUTO_BUCKETS: [i64; 12] = [
+    1,
+    5,
+    10,
+    30,
+    60,
+    5 * 60,
+    10 * 60,
+    30 * 60,
+    60 * 60,
+    6 * 60 * 60,
+    24 * 60 * 60,
+    7 * 24 * 60 * 60,
+];
+const AUTO_MAX_ROWS: i64 = 50;
+/// Runs of empty buckets longer than this are printed as a single row.
+const MAX_EMPTY_ROWS: i64 = 10;
+
+/// Parses durations like `500ms`, `30s`, `1m`, `2h` or `1d`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("Missing unit in duration `{s}`, eg. 1m"))?;
+    let (number, unit) = s.split_at(split);
+    let number: i64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration `{s}`"))?;
+    let duration = match unit {
+        "ms" => Duration::try_milliseconds(number),
+        "s" => Duration::try_seconds(number),
+        "m" => Duration::try_minutes(number),
+        "h" => Duration::try_hours(number),
+        "d" => Duration::try_days(number),
+        _ => return Err(format!("Unknown unit `{unit}` in duration `{s}`")),
+    }
+    .ok_or_else(|| format!("Duration `{s}` is too long"))?;
+    if duration <= Duration::zero() {
+        return Err(format!("Duration `{s}` must be positive"));
+    }
+    Ok(duration)
+}
+
+/// Formats a bucket size the way it's given on the command line.
+fn format_bucket(duration: Duration) -> String {
+    let millis = duration.num_milliseconds();
+    let units = [
+        (24 * 60 * 60 * 1000, "d"),
+        (60 * 60 * 1000, "h"),
+        (60 * 1000, "m"),
+        (1000, "s"),
+    ];
+    for (size, unit) in units {
+        if millis % size == 0 {
+            return format!("{}{unit}", millis / size);
+        }
+    }
+    format!("{millis}ms")
+}
+
+/// Time buckets of one size, aligned in the timezone the times are printed
+/// in, so that eg. daily buckets start at local midnight.
+#[derive(Clone, Copy)]
+pub struct Buckets {
+    pub size: Duration,
+    tz: FixedOffset,
+}
+
+impl Buckets {
+    pub fn new(size: Duration, config: &PrintConfig) -> Self {
+        Buckets {
+            size,
+            tz: config.tz(),
+        }
+    }
+
+    /// Buckets of another size in the same timezone.
+    fn with_size(self, size: Duration) -> Self {
+        Buckets { size, ..self }
+    }
+
+    fn offset_millis(&self) -> i64 {
+        self.tz.local_minus_utc() as i64 * 1000
+    }
+
+    /// The index of the bucket that `time` falls in.
+    pub fn index(&self, time: &DateTime<Utc>) -> i64 {
+        (time.timestamp_millis() + self.offset_millis()).div_euclid(self.size.num_milliseconds())
+    }
+
+    /// The start time of a bucket from [Buckets::index].
+    pub fn label(&self, index: i64) -> String {
+        let size_millis = self.size.num_milliseconds();
+        let format = if size_millis % 1000 == 0 {
+            "%Y-%m-%d %H:%M:%S"
+        } else {
+            "%Y-%m-%d %H:%M:%S%.3f"
+        };
+        // Huge buckets can start before the earliest time there is.
+        index
+            .checked_mul(size_millis)
+            .and_then(|millis| {
+                Utc.timestamp_millis_opt(millis - self.offset_millis())
+                    .single()
+            })
+            .unwrap_or(DateTime::<Utc>::MIN_UTC)
+            .with_timezone(&self.tz)
+            .format(format)
+            .to_string()
+    }
+}
+
+/// Entry counts per time bucket and severity, for `clog histogram`.
+pub struct Histogram {
+    bucket: Option<Duration>,
+    width: usize,
+    /// The buckets that entries are counted in: the given ones, or seconds
+    /// until the size is chosen.
+    keys: Buckets,
+    /// The entry counts per severity and key.
+    counts: BTreeMap<i64, [usize; 6]>,
+}
+
+impl Histogram {
+    pub fn new(bucket: Option<Duration>, width: usize, config: &PrintConfig) -> Self {
+        let key_size = bucket.unwrap_or_else(|| Duration::seconds(1));
+        Histogram {
+            bucket,
+            width,
+            keys: Buckets::new(key_size, config),
+            counts: BTreeMap::new(),
+        }
+    }
+
+    fn bucket_size(&self) -> Duration {
+        if let Some(bucket) = self.bucket {
+            return bucket;
+        }
+        let span = match (self.counts.keys().next(), self.counts.keys().next_back()) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0,
+        };
+        let seconds = AUTO_BUCKETS
+            .iter()
+            .find(|bucket| span / **bucket < AUTO_MAX_ROWS)
+            .unwrap_or(&AUTO_BUCKETS[AUTO_BUCKETS.len() - 1]);
+        Duration::seconds(*seconds)
+    }
+}
+
+impl Report for Histogram {
+    fn add(&mut self, output: &ParserOutput) {
+        if let ParserOutput::Log(line) = output {
+            let key = self.keys.index(&line.time);
+            self.counts.entry(key).or_default()[line.severity() as usize] += 1;
+        }
+    }
+
+    fn print(
+        &self,
+        f: &mut dyn Write,
+        _config: &PrintConfig,
+        _parser: &Parser,
+    ) -> std::io::Result<()> {
+        let bucket_size = self.bucket_size();
+        // The keys are whole seconds when the bucket size was chosen, and all
+        // the sizes to choose from are too.
+        let per_key = bucket_size.num_milliseconds() / self.keys.size.num_milliseconds();
+        let mut buckets: BTreeMap<i64, [usize; 6]> = BTreeMap::new();
+        for (key, counts) in &self.counts {
+            let bucket = buckets.entry(key.div_euclid(per_key)).or_default();
+            for (total, count) in bucket.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+        let (first, last) = match (buckets.keys().next(), buckets.keys().next_back()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Ok(()),
+        };
+        let bucket_times = self.keys.with_size(bucket_size);
+        let label = |bucket: i64| bucket_times.label(bucket);
+
+        let max = buckets
+            .values()
+            .map(|counts| counts.iter().sum::<usize>())
+            .max()
+            .unwrap_or(0);
+        let label_width = label(first).len();
+        let count_width = max.to_string().len();
+        // Leave room for the rounding up of small, non-empty segments.
+        let bar_width = self
+            .width
+            .saturating_sub(label_width + count_width + 4 + Severity::ALL.len())
+            .max(10);
+
+        let legend: Vec<_> = Severity::ALL
+            .iter()
+            .filter(|s| {
+                buckets
+                    .values()
+                    .any(|counts| counts[(*s).clone() as usize] > 0)
+            })
+            .map(|s| {
+                let name = format!("{s:?}").to_uppercase();
+                format!("{} {}", s.colors().1.paint("█"), name)
+            })
+            .collect();
+        writeln!(
+            f,
+            "{:label_width$}  {}",
+            format!("Bucket: {}", format_bucket(bucket_size)).bold(),
+            legend.join("  ")
+        )?;
+
+        let mut bucket = first;
+        while bucket <= last {
+            let counts = match buckets.get(&bucket) {
+                Some(counts) => *counts,
+                None => {
+                    let next = *buckets.range(bucket..).next().unwrap().0;
+                    if next - bucket > MAX_EMPTY_ROWS {
+                        writeln!(f, "{:label_width$}  {} empty", "...", next - bucket)?;
+                        bucket = next;
+                        continue;
+                    }
+                    [0; 6]
+                }
+            };
+            let total: usize = counts.iter().sum();
+            write!(f, "{}  ", theme().time.paint(label(bucket)))?;
+
+            // Round the cumulative counts, so that the segments add up to the
+            // length of the whole bar.
+            let mut cumulative = 0;
+            let mut drawn = 0;
+            for (severity, count) in Severity::ALL.iter().zip(counts) {
+                if count == 0 {
+                    continue;
+                }
+                cumulative += count;
+                let end = (cumulative * bar_width + max / 2) / max;
+                let length = end.saturating_sub(drawn).max(1);
+                drawn += length;
+                write!(f, "{}", severity.colors().1.paint("█".repeat(length)))?;
+            }
+            writeln!(f, " {total:>count_width$}")?;
+            bucket += 1;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::print_report;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(Ok(Duration::minutes(1)), parse_duration("1m"));
+        assert_eq!(Ok(Duration::milliseconds(500)), parse_duration("500ms"));
+        assert!(parse_duration("1").is_err());
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("999999999999d").is_err());
+        assert!(parse_duration("99999999999999999999s").is_err());
+        assert_eq!("90s", format_bucket(Duration::seconds(90)));
+        assert_eq!("2h", format_bucket(Duration::hours(2)));
+    }
+
+    fn histogram(input: &str, bucket: &str) -> String {
+        colored::control::set_override(false);
+        let config = PrintConfig::default();
+        let mut histogram = Histogram::new(parse_duration(bucket).ok(), 38, &config);
+        print_report(&mut histogram, input, &config)
+    }
+
+    #[test]
+    fn stacked_bars_per_bucket() {
+        let input = r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z", "severity": "INFO"}
+{"message": "a", "timestamp": "2022-04-01T18:49:53Z", "severity": "INFO"}
+{"message": "a", "timestamp": "2022-04-01T18:49:59Z", "severity": "ERROR"}
+{"message": "a", "timestamp": "2022-04-01T18:51:10Z", "severity": "INFO"}
+{"message": "a", "timestamp": "2022-04-01T18:51:11Z", "severity": "WARNING"}
+{"message": "a", "timestamp": "2022-04-01T18:51:12Z", "severity": "ERROR"}
+{"message": "a", "timestamp": "2022-04-01T18:51:13Z", "severity": "ERROR"}"#;
+        let expected = r#"Bucket: 1m           █ INFO  █ WARNING  █ ERROR
+2022-04-01 18:49:00  ████████ 3
+2022-04-01 18:50:00   0
+2022-04-01 18:51:00  ██████████ 4
+"#;
+        assert_eq!(expected, histogram(input, "1m"));
+        let expected = r#"Bucket: 5s           █ INFO  █ WARNING  █ ERROR
+2022-04-01 18:49:50  █████ 2
+2022-04-01 18:49:55  ███ 1
+...                  14 empty
+2022-04-01 18:51:10  ██████████ 4
+"#;
+        assert_eq!(expected, histogram(input, "auto"));
+    }
+
+    #[test]
+    fn skips_long_runs_of_empty_buckets() {
+        let input = r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z", "severity": "INFO"}
+{"message": "a", "timestamp": "2022-04-01T18:49:56Z", "severity": "INFO"}
+{"message": "a", "timestamp": "2022-04-01T18:50:52Z", "severity": "INFO"}"#;
+        let expected = r#"Bucket: 1s           █ INFO
+2022-04-01 18:49:52  ██████████ 1
+2022-04-01 18:49:53   0
+2022-04-01 18:49:54   0
+2022-04-01 18:49:55   0
+2022-04-01 18:49:56  ██████████ 1
+...                  55 empty
+2022-04-01 18:50:52  ██████████ 1
+"#;
+        assert_eq!(expected, histogram(input, "1s"));
+    }
+}
@@ -1,13 +1,35 @@
 #![deny(rust_2021_compatibility)]
+mod agg;
+mod color;
+mod dedup;
+mod docker;
+mod fields;
+mod filter;
+mod histogram;
+mod pager;
+mod parallel;
 mod parser;
-
-use std::collections::HashMap;
-use std::hash::DefaultHasher;
+mod prefix;
+mod pretty;
+mod span_tree;
+mod stats;
+mod table;
+mod template;
+mod theme;
+mod top;
+mod tui;
+
+use std::fs::File;
 use std::hash::Hash;
-use std::hash::Hasher;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::mem::take;
+use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
 
 use chrono::prelude::*;
 use chrono::DateTime;
@@ -15,28 +37,90 @@ use chrono::Duration;
 use chrono::Local;
 use chrono::Utc;
 use color_eyre::Result;
-use colored::{Color, Colorize};
-use eyre::bail;
 use eyre::eyre;
 use eyre::Context;
-
-use parser::{root, JsonValue};
+use indexmap::IndexMap;
+use signal_hook::consts::{SIGINT, SIGTERM};
+
+use agg::Aggregation;
+use color::{id_color, ColorChoice, ColorDepth, Style, RED};
+use dedup::{Dedup, DedupMode};
+use docker::Record;
+use fields::FieldStats;
+use histogram::{parse_duration, Histogram};
+use pager::Pager;
+use parser::{Encoding, JsonValue, Map, RawObject, Scan, Scanner};
+use prefix::{find_prefix, Prefix};
+use pretty::{to_json, write_map, FieldOrder};
+use span_tree::{SpanTree, SPAN_KEYS};
+use stats::{format_duration, Stats};
+use table::console_width;
+use theme::{theme, ThemeName};
+use top::TopMessages;
 
 use clap::Parser as ClapParser;
-use clap::ValueEnum as ClapValueEnum;
+use clap::Subcommand as ClapSubcommand;
+
+/// Signals from the terminal that are ignored while the output is paged.
+#[cfg(unix)]
+const PAGER_SIGNALS: [i32; 2] = [SIGINT, signal_hook::consts::SIGQUIT];
+#[cfg(not(unix))]
+const PAGER_SIGNALS: [i32; 1] = [SIGINT];
+
+/// How often waiting for input is interrupted to check for signals.
+const INTERRUPT_POLL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// The message length `--oneline` truncates at when the width is unknown.
+const ONELINE_MAX_LENGTH: usize = 100;
+/// The narrowest message column to wrap or truncate messages to.
+const MIN_WRAP_WIDTH: usize = 20;
 
 #[derive(Debug)]
 struct LogLine {
     pub time: DateTime<Utc>,
     pub severity: String,
-    pub parsed_map: HashMap<String, JsonValue>,
+    /// The entry as it was read. Its fields are parsed when they are looked
+    /// up, so that printing the first line of an entry allocates little.
+    raw: RawObject,
+    /// Fields from around the JSON, like the prefix of the line, that are
+    /// used when the entry doesn't have them.
+    pub labels: Vec<(String, String)>,
+    /// Set by `--dedup` when this entry stands for a run of duplicates: the
+    /// number of entries and the time between the first and the last.
+    pub repeated: Option<(usize, Duration)>,
 }
 
+#[derive(Clone)]
 struct PrintConfig {
     pub extra: Vec<String>,
     pub verbose: bool,
     pub is_local_timezone: bool,
-    pub oneline_maxlength: Option<usize>,
+    pub oneline: bool,
+    /// The width to fit entries in, by truncating them with `oneline` and
+    /// wrapping them otherwise.
+    pub max_width: Option<usize>,
+    /// Print nested objects and arrays in verbose mode as inline JSON.
+    pub compact: bool,
+    /// Print fields in verbose mode sorted by key, instead of in the order
+    /// they were logged.
+    pub sort_fields: bool,
+    pub span_tree: bool,
+}
+
+/// The command line's defaults, except that times are printed in UTC.
+impl Default for PrintConfig {
+    fn default() -> Self {
+        PrintConfig {
+            extra: vec![],
+            verbose: false,
+            is_local_timezone: false,
+            oneline: false,
+            max_width: None,
+            compact: false,
+            sort_fields: true,
+            span_tree: false,
+        }
+    }
 }
 
 impl PrintConfig {
@@ -47,9 +131,32 @@ impl PrintConfig {
             Utc.fix()
         }
     }
+
+    fn format_time(&self, time: &DateTime<Utc>) -> String {
+        let t = time.with_timezone(&self.tz());
+        // Formatting the fields directly is several times faster than a
+        // format string, which matters when printing millions of entries.
+        let mut formatted = match t.year() {
+            0..=9999 => format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+                t.year(),
+                t.month(),
+                t.day(),
+                t.hour(),
+                t.minute(),
+                t.second(),
+                t.nanosecond() % 1_000_000_000 / 1_000_000
+            ),
+            _ => t.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+        };
+        if !self.is_local_timezone {
+            formatted.push('Z');
+        }
+        formatted
+    }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 enum Severity {
     Tracing,
     Debug,
@@ -59,11 +166,29 @@ enum Severity {
     Fatal,
 }
 
+impl Severity {
+    const ALL: [Severity; 6] = [
+        Severity::Tracing,
+        Severity::Debug,
+        Severity::Info,
+        Severity::Warning,
+        Severity::Error,
+        Severity::Fatal,
+    ];
+
+    /// The (severity, message) styles used when printing an entry.
+    fn colors(&self) -> (Style, Style) {
+        let theme = theme();
+        let i = self.clone() as usize;
+        (theme.severities[i], theme.messages[i])
+    }
+}
+
 impl FromStr for Severity {
     type Err = eyre::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        return match s.to_lowercase().as_ref() {
+        match s.to_lowercase().as_ref() {
             "tracing" | "trace" => Ok(Self::Tracing),
             "debug" => Ok(Self::Debug),
             "info" => Ok(Self::Info),
@@ -71,174 +196,181 @@ impl FromStr for Severity {
             "error" => Ok(Self::Error),
             "fatal" => Ok(Self::Fatal),
             _ => Err(eyre!("Unknown severity: {}", s)),
-        };
+        }
     }
 }
 
-fn is_too_dark(r: u8, g: u8, b: u8) -> bool {
-    let luma = 0.2126 * (r as f64) + 0.7152 * (g as f64) + 0.0722 * (b as f64); // per ITU-R BT.709
-    luma < 80.0
-}
-
-fn random_color(input: &str) -> String {
-    let mut hasher = DefaultHasher::new();
-    input.hash(&mut hasher);
-    let hash_value = hasher.finish();
-    let red = (hash_value & 0xFF) as u8;
-    let green = ((hash_value >> 8) & 0xFF) as u8;
-    let blue = ((hash_value >> 16) & 0xFF) as u8;
-    let bold = if ((hash_value >> 24) & 0x1) == 0 {
-        "1"
-    } else {
-        ""
-    };
-    let foreground = if is_too_dark(red, green, blue) {
-        "48;2;255;255;255"
-    } else {
-        "40"
-    };
-
-    format!("\x1b[{bold};{foreground};38;2;{red};{green};{blue}m")
-}
-
 impl LogLine {
     fn print<W>(&self, f: &mut W, config: &PrintConfig) -> std::io::Result<()>
     where
         W: Write,
     {
-        let time_in_timezone = self.time.with_timezone(&config.tz());
-        let time_in_timezone = time_in_timezone.format("%Y-%m-%d %H:%M:%S%.3f");
-        write!(f, "{}", time_in_timezone.to_string().green())?;
-        if !config.is_local_timezone {
-            write!(f, "{}", "Z".green())?;
+        let theme = theme();
+        let mut prefix = Vec::new();
+        write!(
+            prefix,
+            "{}",
+            theme.time.paint(config.format_time(&self.time))
+        )?;
+        if let Some(source) = self.label("source") {
+            write!(prefix, " [{}]", id_color(source))?;
         }
         // process id or request_id
-        if let Some(process_id) = self.value(&self.parsed_map, "context.processId") {
+        if let Some(process_id) = self.value("context.processId") {
             let max_len = std::cmp::min(process_id.len(), 6);
             let process_id = process_id[..max_len].to_string();
-            write!(f, " [p={:6}]", process_id.bold())?;
-        } else if let Some(request_id) = self.value(&self.parsed_map, "context.requestId") {
+            write!(prefix, " [p={:6}]", theme.id.paint(process_id))?;
+        } else if let Some(request_id) = self.value("context.requestId") {
             let max_len = std::cmp::min(request_id.len(), 8);
             let request_id = request_id[..max_len].to_string();
-            let color = random_color(&request_id);
-
-            write!(f, " [{color}{:<8}\x1b[0m]", request_id)?;
+            write!(prefix, " [{:<8}]", id_color(&request_id))?;
         }
 
-        let extra_colors = vec![Color::BrightBlack, Color::BrightCyan, Color::BrightMagenta];
         for (i, e) in config.extra.iter().enumerate() {
-            let color = extra_colors[i % extra_colors.len()];
-            if let Some(app) = self.value(&self.parsed_map, e) {
-                write!(f, " [{}]", app.color(color))?;
+            let style = theme.extras[i % theme.extras.len()];
+            let value = self.lookup(e);
+            if let Some(app) = value.and_then(|value| format_value(&value, config.sort_fields)) {
+                write!(prefix, " [{}]", style.paint(app))?;
             } else {
-                write!(f, " []")?;
+                write!(prefix, " []")?;
             }
         }
 
-        let (severity_style, message_style) = match self.severity() {
-            Severity::Tracing => (Color::BrightBlack, Color::BrightBlack),
-            Severity::Debug => (Color::BrightBlack, Color::BrightBlack),
-            Severity::Info => (Color::BrightBlack, Color::White),
-            Severity::Warning => (Color::Yellow, Color::Yellow),
-            Severity::Error => (Color::Red, Color::Red),
-            Severity::Fatal => (Color::Magenta, Color::Magenta),
-        };
+        let (severity_style, message_style) = self.severity().colors();
 
         write!(
-            f,
+            prefix,
             " {:7}",
-            self.severity.to_uppercase().color(severity_style).bold()
+            severity_style.paint(self.severity.to_uppercase())
         )?;
+        f.write_all(&prefix)?;
+        let prefix_width = console_width(&String::from_utf8_lossy(&prefix));
         let (message_path, message) = self
-            .get_message(config)
+            .get_message(config, prefix_width)
             .map(|x| (Some(x.0), x.1))
             .unwrap_or((None, String::new()));
 
-        for line in message.lines() {
-            writeln!(f, " {}", line.color(message_style))?;
+        // Continuation lines, and wrapped parts of long lines, are aligned
+        // under the start of the message.
+        let wrap_width = match config.max_width {
+            Some(max_width) if !config.oneline => max_width.saturating_sub(prefix_width + 1),
+            _ => usize::MAX,
+        };
+        let rows = message
+            .lines()
+            .flat_map(|line| wrap(line, wrap_width.max(MIN_WRAP_WIDTH)));
+        for (i, row) in rows.enumerate() {
+            if i > 0 {
+                write!(f, "{:prefix_width$}", "")?;
+            }
+            write!(f, " {}", message_style.paint(row))?;
+            if let (0, Some((count, duration))) = (i, self.repeated) {
+                let repeated = format!("(×{count} over {})", format_duration(duration));
+                write!(f, " {}", theme.dim.paint(repeated))?;
+            }
+            writeln!(f)?;
         }
-        if config.verbose {
-            write_logline_map(f, &self.parsed_map, &String::from("  "), message_path)?;
+        if config.verbose && config.span_tree {
+            // The span fields are already shown in the span headers.
+            let mut fields = self.fields();
+            for key in SPAN_KEYS {
+                fields.shift_remove(key);
+            }
+            write_map(f, &fields, "  ", message_path, config)?;
+        } else if config.verbose {
+            write_map(f, &self.fields(), "  ", message_path, config)?;
         }
         Ok(())
     }
 
-    fn get_message(&self, config: &PrintConfig) -> Option<(&'static str, String)> {
+    /// The message to print after a prefix of `prefix_width` columns and a
+    /// space.
+    fn get_message(
+        &self,
+        config: &PrintConfig,
+        prefix_width: usize,
+    ) -> Option<(&'static str, String)> {
+        let (message_path, message) = self.raw_message()?;
+        let message = match (config.oneline, config.max_width) {
+            (true, Some(max_width)) => oneline(
+                &message,
+                max_width
+                    .saturating_sub(prefix_width + 1)
+                    .max(MIN_WRAP_WIDTH),
+            ),
+            (true, None) => oneline(&message, ONELINE_MAX_LENGTH),
+            (false, _) => message,
+        };
+        Some((message_path, message))
+    }
+
+    /// All the fields of the entry.
+    fn fields(&self) -> Map<'_> {
+        let mut fields = self.raw.map();
+        for (key, value) in &self.labels {
+            fields
+                .entry(key.as_str().into())
+                .or_insert_with(|| JsonValue::Str(value.as_str().into()));
+        }
+        fields
+    }
+
+    fn label(&self, key: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label, _)| *label == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Looks up a dotted key path like `context.requestId`.
+    fn lookup(&self, key: &str) -> Option<JsonValue<'_>> {
+        let (first, rest) = match key.split_once('.') {
+            Some((first, rest)) => (first, Some(rest)),
+            None => (key, None),
+        };
+        let value = match self.raw.get(first) {
+            Some(value) => value,
+            None => JsonValue::Str(self.label(first)?.into()),
+        };
+        match (rest, value) {
+            (None, value) => Some(value),
+            (Some(rest), JsonValue::Object(map)) => lookup(&map, rest).cloned(),
+            _ => None,
+        }
+    }
+
+    /// The value at a dotted key path, formatted for printing. The keys of
+    /// objects are sorted, so that equal values are formatted the same.
+    fn value(&self, key: &str) -> Option<String> {
+        self.lookup(key)
+            .as_ref()
+            .and_then(|value| format_value(value, true))
+    }
+
+    /// The trimmed message including any exception info, and the key it was
+    /// found under.
+    fn raw_message(&self) -> Option<(&'static str, String)> {
         let search_places = ["message", "msg", "event", "MessageTemplate"];
         for sp in search_places {
-            if let Some(value) = self.parsed_map.get(sp) {
+            if let Some(value) = self.raw.get(sp) {
                 if let Ok(str_value) = value.str_value() {
                     // Add info from exc_info
-                    let exception_message =
-                        if let Some(exception_message) = self.parsed_map.get("exc_info") {
-                            if let Ok(exception_message) = exception_message.str_value() {
-                                Some(exception_message)
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        };
-
-                    let message = match exception_message {
-                        Some(exc) => format!("{}\n{}", str_value, exc),
-                        None => str_value,
-                    };
-
-                    let message = message.trim();
-                    let message = if let Some(max_length) = config.oneline_maxlength {
-                        let message = message.replace("\n", " \u{2936} ");
-                        if message.len() > max_length {
-                            let mut end: usize = 0;
-                            message
-                                .chars()
-                                .into_iter()
-                                .take(max_length - 3)
-                                .for_each(|x| end += x.len_utf8());
-                            format!("{}...", &message[..end])
-                        } else {
-                            message
-                        }
-                    } else {
-                        message.replace("\n", "\n    ")
+                    let exception_message = self.raw.get("exc_info");
+                    let message = match exception_message.as_ref().map(|e| e.str_value()) {
+                        Some(Ok(exc)) => format!("{}\n{}", str_value, exc),
+                        _ => str_value.to_string(),
                     };
 
-                    return Some((sp, message));
+                    return Some((sp, message.trim().to_string()));
                 }
             }
         }
-        return None;
-    }
-
-    fn value(&self, map: &HashMap<String, JsonValue>, key: &str) -> Option<String> {
-        let parts: Vec<_> = key.split(".").collect();
-        let parts_len = parts.len();
-        let mut map = map;
-        for (i, part) in parts.into_iter().enumerate() {
-            let is_last = i == parts_len - 1;
-            let part_value = map.get(part);
-            if is_last {
-                return match part_value {
-                    Some(JsonValue::Object(m)) => Some(format!("{:?}", m)),
-                    Some(JsonValue::Num(n)) => Some(format!("{}", n)),
-                    Some(JsonValue::Str(s)) => Some(format!("{}", s)),
-                    Some(JsonValue::Bool(b)) => Some(format!("{}", b)),
-                    Some(JsonValue::Array(value)) => Some(format!("{:?}", value)),
-                    Some(JsonValue::Null) => None,
-                    None => None,
-                };
-            } else if let Some(JsonValue::Object(m)) = part_value {
-                map = m
-            } else {
-                return None;
-            }
-        }
-        panic!("Unreachable")
+        None
     }
 
     fn severity(&self) -> Severity {
         let severity = self.severity.to_lowercase();
-        return if severity.contains("warn") {
+        if severity.contains("warn") {
             Severity::Warning
         } else if severity.contains("error") || severity.contains("critical") {
             Severity::Error
@@ -250,53 +382,111 @@ impl LogLine {
             Severity::Fatal
         } else {
             Severity::Info
-        };
+        }
     }
 }
 
-fn write_logline_map<W>(
-    f: &mut W,
-    map: &HashMap<String, JsonValue>,
-    indent: &str,
-    message_path: Option<&str>,
-) -> std::io::Result<()>
-where
-    W: Write,
-{
-    let mut sorted_keys: Vec<_> = map.keys().clone().into_iter().collect();
-    sorted_keys.sort();
-    for key in sorted_keys.into_iter() {
-        if key == "timestamp" || key == "@timestamp" || key == "severity" || key == "level" {
-            continue;
-        }
-        let value = match &map[key] {
-            JsonValue::Null => None,
-            JsonValue::Num(n) => Some(format!("{}", n)),
-            JsonValue::Str(s) => {
-                if s.contains("\n") {
-                    let line_prefix = format!("\n{indent}  ");
-                    Some(format!("{line_prefix}{}", s.replace("\n", &line_prefix)))
-                } else {
-                    Some(format!("{s}"))
-                }
-            }
-            JsonValue::Bool(b) => Some(format!("{}", b)),
-            JsonValue::Array(value) => Some(format!("{:?}", value)),
-            JsonValue::Object(map) => {
-                if map.len() != 0 {
-                    writeln!(f, "{}{}:", indent, key.bright_black())?;
-                    write_logline_map(f, map, &format!("  {}", indent), message_path)?;
-                }
-                None
-            }
+/// Joins the lines of `message` and truncates it to `max_length` characters.
+fn oneline(message: &str, max_length: usize) -> String {
+    let message = message.replace("\n", " \u{2936} ");
+    if message.chars().count() > max_length {
+        let mut end: usize = 0;
+        message
+            .chars()
+            .take(max_length - 3)
+            .for_each(|x| end += x.len_utf8());
+        format!("{}...", &message[..end])
+    } else {
+        message
+    }
+}
+
+/// Splits `line` into rows of at most `width` characters, at spaces where
+/// possible.
+fn wrap(line: &str, width: usize) -> Vec<&str> {
+    let mut rows = vec![];
+    let mut rest = line;
+    while let Some((limit, _)) = rest.char_indices().nth(width) {
+        let split = if rest[limit..].starts_with(' ') {
+            Some(limit)
+        } else {
+            rest[..limit].rfind(' ')
         };
-        if let Some(value) = value {
-            if Some(key.as_str()) != message_path {
-                writeln!(f, "{}{} = {}", indent, key.bright_black(), value)?;
+        match split.filter(|i| !rest[..*i].trim().is_empty()) {
+            Some(i) => {
+                rows.push(rest[..i].trim_end());
+                rest = rest[i..].trim_start_matches(' ');
+            }
+            None => {
+                rows.push(&rest[..limit]);
+                rest = &rest[limit..];
             }
         }
     }
-    Ok(())
+    rows.push(rest);
+    rows
+}
+
+/// Parses `--max-width`: `auto` for the terminal width, or a number of columns.
+fn parse_max_width(s: &str) -> Result<MaxWidth, String> {
+    match s {
+        "auto" => Ok(MaxWidth::Auto),
+        _ => match s.parse() {
+            Ok(0) | Err(_) => Err(format!("Expected auto or a number of columns, got `{s}`")),
+            Ok(columns) => Ok(MaxWidth::Columns(columns)),
+        },
+    }
+}
+
+/// Parses a limit like `--max-entry-lines`, which must be at least 1.
+fn parse_limit(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(0) | Err(_) => Err(format!("Expected a number above 0, got `{s}`")),
+        Ok(limit) => Ok(limit),
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum MaxWidth {
+    Auto,
+    Columns(usize),
+}
+
+/// The width of the terminal stdout is connected to, or 80 when it's not a
+/// terminal.
+fn terminal_width() -> usize {
+    if let Some((terminal_size::Width(width), _)) = terminal_size::terminal_size() {
+        return width as usize;
+    }
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Looks up a dotted key path like `context.requestId` in a parsed log entry.
+fn lookup<'a, 'b>(map: &'a Map<'b>, key: &str) -> Option<&'a JsonValue<'b>> {
+    let (first, rest) = match key.split_once('.') {
+        Some((first, rest)) => (first, Some(rest)),
+        None => (key, None),
+    };
+    match (map.get(first), rest) {
+        (value, None) => value,
+        (Some(JsonValue::Object(m)), Some(rest)) => lookup(m, rest),
+        _ => None,
+    }
+}
+
+/// Formats `value` for printing, with the keys of objects sorted if `sort`.
+/// None for `null`.
+fn format_value(value: &JsonValue, sort: bool) -> Option<String> {
+    match value {
+        JsonValue::Object(_) | JsonValue::Array(_) => Some(to_json(value, sort)),
+        JsonValue::Num(n) => Some(format!("{}", n)),
+        JsonValue::Str(s) => Some(s.to_string()),
+        JsonValue::Bool(b) => Some(format!("{}", b)),
+        JsonValue::Null => None,
+    }
 }
 
 fn bunyan_to_level(level: i32) -> &'static str {
@@ -310,54 +500,63 @@ fn bunyan_to_level(level: i32) -> &'static str {
     }
 }
 
-fn get_log_line(parsed: JsonValue) -> Result<LogLine> {
-    let parsed = match parsed {
-        JsonValue::Object(_) => parsed,
-        _ => bail!("parsed is not a JsonValue::Object"),
-    };
-    let time_json = parsed
-        .map_value("timestamp")
-        .or_else(|_| parsed.map_value("time"))
-        .or_else(|_| parsed.map_value("eventTime"))
-        .or_else(|_| parsed.map_value("@timestamp"))
-        .or_else(|_| parsed.map_value("Timestamp"))
-        .or_else(|_| parsed.map_value("ts"))?;
-
-    let time: DateTime<Utc> = if let Ok(time_str) = time_json.str_value() {
-        DateTime::parse_from_str(&time_str, "%+")
+fn parse_time(time_json: &JsonValue) -> Result<DateTime<Utc>> {
+    if let Ok(time_str) = time_json.str_value() {
+        Ok(DateTime::parse_from_str(time_str, "%+")
             .map(|dt| dt.with_timezone(&Utc))
-            .context(format!("Failed to parse datetime: `{}`", &time_str))?
+            .context(format!("Failed to parse datetime: `{}`", &time_str))?)
     } else {
         let seconds_value = time_json.map_value("seconds")?.int_value()?;
         let nanos_value = time_json.map_value("nanos")?.int_value()?;
         let start = Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap();
-        let duration = Duration::try_seconds(seconds_value as i64).unwrap()
-            + Duration::nanoseconds(nanos_value as i64);
-        start + duration
-    };
+        let duration = Duration::try_seconds(seconds_value)
+            .ok_or_else(|| eyre!("Timestamp out of range: {} seconds", seconds_value))?
+            + Duration::nanoseconds(nanos_value);
+        Ok(start + duration)
+    }
+}
 
-    let severity = parsed
-        .map_value("severity")
-        .and_then(|x| x.str_value())
-        .or_else(|_| {
-            parsed
-                .map_value("level")
-                .and_then(|level| level.int_value())
-                .and_then(|level| Ok(bunyan_to_level(level as i32).to_string()))
-        })
-        .or_else(|_| parsed.map_value("level").and_then(|x| x.str_value()))
-        .or_else(|_| parsed.map_value("log.level").and_then(|x| x.str_value()))
-        .unwrap_or_else(|_| "unknown".to_string());
+/// Reads `object` as a log entry, at `default_time` if it has no timestamp.
+fn get_log_line(object: RawObject, default_time: Option<DateTime<Utc>>) -> Result<LogLine> {
+    let time_keys = [
+        "timestamp",
+        "time",
+        "eventTime",
+        "@timestamp",
+        "Timestamp",
+        "ts",
+    ];
+    let time = match (
+        time_keys.iter().find_map(|key| object.get(key)),
+        default_time,
+    ) {
+        (Some(time_json), _) => parse_time(&time_json)?,
+        (None, Some(time)) => time,
+        (None, None) => return Err(eyre!("No timestamp in any of {:?}", time_keys)),
+    };
 
-    let map = match parsed {
-        JsonValue::Object(map) => map,
-        _ => bail!("Parsed is not a map"),
+    let str_value = |key| {
+        object
+            .get(key)
+            .and_then(|value| value.str_value().ok().map(str::to_string))
     };
+    let severity = str_value("severity")
+        .or_else(|| {
+            object
+                .get("level")
+                .and_then(|level| level.int_value().ok())
+                .map(|level| bunyan_to_level(level as i32).to_string())
+        })
+        .or_else(|| str_value("level"))
+        .or_else(|| str_value("log.level"))
+        .unwrap_or_else(|| "unknown".to_string());
 
     Ok(LogLine {
         time,
         severity,
-        parsed_map: map,
+        raw: object,
+        labels: vec![],
+        repeated: None,
     })
 }
 
@@ -381,10 +580,145 @@ impl ParserOutput {
     }
 }
 
-#[derive(Default, Debug)]
+/// Prints parser outputs, applying the options that decide which entries are
+/// shown and how.
+struct Printer {
+    config: PrintConfig,
+    min_severity: Option<Severity>,
+    span_tree: SpanTree,
+    dedup: Option<Dedup>,
+}
+
+impl Printer {
+    fn new(config: PrintConfig) -> Self {
+        Printer {
+            config,
+            min_severity: None,
+            span_tree: SpanTree::default(),
+            dedup: None,
+        }
+    }
+
+    fn push<W>(&mut self, f: &mut W, output: ParserOutput) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        if !self.shows(&output) {
+            return Ok(());
+        }
+        match &mut self.dedup {
+            Some(dedup) => {
+                for output in dedup.push(output) {
+                    self.print(f, &output)?;
+                }
+            }
+            None => self.print(f, &output)?,
+        }
+        Ok(())
+    }
+
+    /// Whether `output` passes the severity filter.
+    fn shows(&self, output: &ParserOutput) -> bool {
+        match (&self.min_severity, output) {
+            (Some(minimum), ParserOutput::Log(line)) => line.severity() >= *minimum,
+            _ => true,
+        }
+    }
+
+    /// When a held back entry should be printed if no more input arrives.
+    fn deadline(&self) -> Option<Instant> {
+        self.dedup.as_ref().and_then(|dedup| dedup.deadline())
+    }
+
+    /// Prints any held back entry.
+    fn flush<W>(&mut self, f: &mut W) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        if let Some(output) = self.dedup.as_mut().and_then(|dedup| dedup.flush()) {
+            self.print(f, &output)?;
+        }
+        f.flush()
+    }
+
+    fn print<W>(&mut self, f: &mut W, output: &ParserOutput) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        if self.config.span_tree {
+            self.span_tree.print(f, output, &self.config)
+        } else {
+            output.print(f, &self.config)
+        }
+    }
+}
+
+/// Consumes every parser output and prints a summary once the input ends.
+trait Report {
+    fn add(&mut self, output: &ParserOutput);
+
+    fn print(
+        &self,
+        f: &mut dyn Write,
+        config: &PrintConfig,
+        parser: &Parser,
+    ) -> std::io::Result<()>;
+}
+
+/// Reads `input` a line at a time into `report`, and returns what it prints.
+#[cfg(test)]
+fn print_report(report: &mut dyn Report, input: &str, config: &PrintConfig) -> String {
+    let mut parser = Parser::new();
+    for output in parser.push_lines(input) {
+        report.add(&output);
+    }
+    let mut buffer = Vec::new();
+    report.print(&mut buffer, config, &parser).unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+/// How many bytes an entry may take before it's given up on as text. This is
+/// what bounds the memory an unfinished entry takes.
+const MAX_ENTRY_BYTES: usize = 16 << 20;
+/// How many lines an entry may span before it's given up on as text, which is
+/// high enough for any pretty-printed entry that fits in [MAX_ENTRY_BYTES].
+const MAX_ENTRY_LINES: usize = 1_000_000;
+
+#[derive(Debug)]
 struct Parser {
     buffer: String,
     pub debug: bool,
+    /// Number of inputs that looked like JSON but couldn't be read as a log entry.
+    pub failures: usize,
+    /// Limits for an unfinished entry, after which the buffer is printed as
+    /// text, so that a `{` that's never closed can't swallow the rest of the
+    /// input.
+    pub max_entry_lines: usize,
+    pub max_entry_bytes: usize,
+    pub encoding: Encoding,
+    /// Set when the input should end, which stops parsing what's buffered
+    /// so that it's printed as it is.
+    pub interrupted: Arc<AtomicBool>,
+    scanner: Scanner,
+    /// The start of long lines that Docker log records or CRI lines split in
+    /// parts, per stream, until the parts with the rest of them.
+    partials: IndexMap<(String, String), String>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Parser {
+            buffer: String::new(),
+            debug: false,
+            failures: 0,
+            max_entry_lines: MAX_ENTRY_LINES,
+            max_entry_bytes: MAX_ENTRY_BYTES,
+            encoding: Encoding::default(),
+            interrupted: Arc::default(),
+            scanner: Scanner::default(),
+            partials: IndexMap::new(),
+        }
+    }
 }
 
 impl Parser {
@@ -393,72 +727,342 @@ impl Parser {
     }
 
     fn flush(&mut self) -> ParserOutput {
-        if self.buffer.is_empty() {
+        self.scanner = Scanner::default();
+        let mut text = take(&mut self.buffer);
+        for (_, partial) in self.partials.drain(..) {
+            text.push_str(&partial);
+            text.push('\n');
+        }
+        if text.is_empty() {
             ParserOutput::None
         } else {
-            ParserOutput::Text(take(&mut self.buffer))
+            ParserOutput::Text(text)
         }
     }
 
-    fn push(&mut self, line: &str) -> Vec<ParserOutput> {
-        use nom::Err::{Error, Failure, Incomplete};
-
-        self.buffer.push_str(line);
+    /// Reads a complete object as a log entry, or as `text` if it isn't one.
+    /// The entry gets the `labels` from around it, and `time` if it has no
+    /// timestamp.
+    fn parse(
+        &mut self,
+        object: RawObject,
+        text: &str,
+        time: Option<DateTime<Utc>>,
+        labels: Vec<(String, String)>,
+    ) -> ParserOutput {
+        match get_log_line(object, time) {
+            Ok(mut x) => {
+                x.labels = labels;
+                ParserOutput::Log(x)
+            }
+            Err(e) => {
+                self.failures += 1;
+                if self.debug {
+                    eprintln!("Failed get_log_line: {}", Style::fg(RED).paint(e))
+                }
+                ParserOutput::Text(text.to_string())
+            }
+        }
+    }
 
-        let result = root(&self.buffer);
-        match result {
-            Ok((rest, value)) => {
-                let output = match get_log_line(value) {
-                    Ok(x) => ParserOutput::Log(x),
-                    Err(e) => {
-                        if self.debug {
-                            eprintln!("Failed get_log_line: {:?}", e.to_string().red())
-                        }
-                        ParserOutput::Text(self.buffer.clone())
-                    }
+    /// Reads a line with a text prefix as an entry, or as text if there's no
+    /// complete object after the prefix. CRI lines that are split in parts
+    /// are read once the last part is.
+    fn parse_prefixed(&mut self, line: &str, prefix: Prefix) -> Option<ParserOutput> {
+        let (head, mut json) = line.split_at(prefix.len);
+        let joined = match prefix.label("stream") {
+            Some(stream)
+                if prefix.partial
+                    || self
+                        .partials
+                        .keys()
+                        .any(|key| key.0 == "cri" && key.1 == stream) =>
+            {
+                let part = match prefix.partial {
+                    true => json.trim_end_matches('\n'),
+                    false => json,
                 };
-                let rest = rest.trim_start_matches('\n').to_string();
-                self.buffer.clear();
-                let mut output = vec![output];
-                for next_output in self.push(&rest) {
-                    match next_output {
-                        ParserOutput::None => (),
-                        _ => output.push(next_output),
+                Some(self.join_partial("cri", stream, part, !prefix.partial)?)
+            }
+            _ => None,
+        };
+        if let Some(joined) = &joined {
+            json = joined;
+        }
+        let text = || match &joined {
+            Some(joined) => format!("{}{}", head, joined),
+            None => line.to_string(),
+        };
+        let mut scanner = Scanner::default();
+        match scanner.scan(json) {
+            Scan::Complete(end) if json[end..].trim().is_empty() => {
+                let object = scanner.into_object(&json[..end]);
+                Some(self.parse(object, &text(), prefix.time, prefix.labels))
+            }
+            _ => Some(ParserOutput::Text(text())),
+        }
+    }
+
+    /// Adds a part of a line of `stream` from `source`, and returns the whole
+    /// line when this is the `last` part, or when the line has grown too long.
+    fn join_partial(
+        &mut self,
+        source: &str,
+        stream: &str,
+        part: &str,
+        last: bool,
+    ) -> Option<String> {
+        let key = (source.to_string(), stream.to_string());
+        let partial = self.partials.entry(key.clone()).or_default();
+        partial.push_str(part);
+        if !last && partial.len() <= self.max_entry_bytes {
+            return None;
+        }
+        self.partials.shift_remove(&key)
+    }
+
+    /// Reads the line in a Docker log record as an entry or text, once the
+    /// records it was split over are all read.
+    fn parse_docker(&mut self, record: Record) -> Option<ParserOutput> {
+        let last = record.log.ends_with('\n');
+        let mut line = self.join_partial(&record.source(), &record.stream, &record.log, last)?;
+        if !line.ends_with('\n') {
+            line.push('\n');
+        }
+        let mut labels = vec![("stream".to_string(), record.stream)];
+        labels.extend(record.attrs);
+        let mut scanner = Scanner::default();
+        match scanner.scan(&line) {
+            Scan::Complete(end) if line[end..].trim().is_empty() => {
+                let object = scanner.into_object(&line[..end]);
+                Some(self.parse(object, &line, record.time, labels))
+            }
+            _ => Some(ParserOutput::Text(line)),
+        }
+    }
+
+    /// Gives up on the entry at the start of `rest`, which is output as text
+    /// up to the next line that could start another entry. Returns how much
+    /// of `rest` that is, which is at least its first line.
+    fn resync(&mut self, rest: &str, problem: &str, output: &mut Vec<ParserOutput>) -> usize {
+        self.failures += 1;
+        if self.debug {
+            eprintln!("Parsing failure: {}", Style::fg(RED).paint(problem));
+        }
+        let open = rest.find('{').map_or(0, |i| i + 1);
+        let first_line = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        let end = rest[open..]
+            .find("\n{")
+            .map_or(rest.len(), |i| open + i + 1)
+            .max(first_line);
+        output.push(ParserOutput::Text(rest[..end].to_string()));
+        self.scanner = Scanner::default();
+        end
+    }
+
+    /// Adds raw input, whole lines of it, so that bytes that aren't valid in
+    /// the encoding never stop the stream.
+    fn push_bytes(&mut self, bytes: Vec<u8>) -> Vec<ParserOutput> {
+        let mut text = self.encoding.decode_owned(bytes);
+        if text.contains('\r') {
+            text = text.replace("\r\n", "\n");
+        }
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        self.push_text(text)
+    }
+
+    /// Adds input to the buffer, and returns the entries it completes.
+    #[cfg(test)]
+    fn push(&mut self, text: &str) -> Vec<ParserOutput> {
+        self.push_text(text.to_string())
+    }
+
+    /// Adds `input` a line at a time, the way it's read, and returns all the
+    /// outputs including the unfinished entry at the end.
+    #[cfg(test)]
+    fn push_lines(&mut self, input: &str) -> Vec<ParserOutput> {
+        let mut outputs = vec![];
+        for line in input.lines() {
+            outputs.extend(self.push(&format!("{line}\n")));
+        }
+        match self.flush() {
+            ParserOutput::None => {}
+            output => outputs.push(output),
+        }
+        outputs
+    }
+
+    /// Adds input to the buffer, and returns the entries it completes. Each
+    /// part of the input is only scanned once, so an entry spanning many
+    /// lines costs no more than one on a single line. The entries share the
+    /// input instead of copying their text out of it.
+    fn push_text(&mut self, text: String) -> Vec<ParserOutput> {
+        let buffer = match self.buffer.is_empty() {
+            true => text,
+            false => {
+                let mut buffer = take(&mut self.buffer);
+                buffer.push_str(&text);
+                buffer
+            }
+        };
+        let buffer = Arc::new(buffer);
+
+        let mut output = vec![];
+        let mut start = 0;
+        while !self.interrupted.load(Ordering::Relaxed) {
+            let rest = &buffer[start..];
+            match self.scanner.scan(rest) {
+                Scan::Incomplete
+                    if !rest.is_empty()
+                        && (self.scanner.lines() >= self.max_entry_lines
+                            || rest.len() > self.max_entry_bytes) =>
+                {
+                    let problem = format!(
+                        "Unfinished entry of {} lines and {} bytes",
+                        self.scanner.lines(),
+                        rest.len()
+                    );
+                    start += self.resync(rest, &problem, &mut output);
+                }
+                Scan::Incomplete => break,
+                Scan::Complete(end) => {
+                    let range = start..start + end;
+                    let object = take(&mut self.scanner).into_object_in(&buffer, range);
+                    let rest = &rest[end..];
+                    let newlines = rest.len() - rest.trim_start_matches('\n').len();
+                    let text = &buffer[start..start + end + newlines];
+                    match Record::read(&object) {
+                        Some(record) => output.extend(self.parse_docker(record)),
+                        None => output.push(self.parse(object, text, None, vec![])),
                     }
+                    start += end + newlines;
                 }
-                output
-            }
-            Err(Incomplete(_)) => vec![],
-            Err(Failure(_)) | Err(Error(_)) => {
-                if self.debug {
-                    eprintln!("Parsing failure: {:?}", format!("{:?}", result).red());
+                Scan::Invalid(pos) => {
+                    let problem = format!("Invalid JSON at byte {}", pos);
+                    start += self.resync(rest, &problem, &mut output);
+                }
+                Scan::Text => {
+                    // Each line is read on its own, since the JSON of a line
+                    // can come after a prefix.
+                    let line = &rest[..rest.find('\n').map_or(rest.len(), |i| i + 1)];
+                    match find_prefix(line) {
+                        Some(prefix) => output.extend(self.parse_prefixed(line, prefix)),
+                        None => output.push(ParserOutput::Text(line.to_string())),
+                    }
+                    start += line.len();
+                    self.scanner = Scanner::default();
                 }
-                let output = ParserOutput::Text(self.buffer.clone());
-                self.buffer.clear();
-                vec![output]
             }
         }
+        // Keep what's left for the next input, without copying it when no
+        // entry was read from the buffer.
+        self.buffer = match Arc::try_unwrap(buffer) {
+            Ok(mut buffer) => {
+                buffer.drain(..start);
+                buffer
+            }
+            Err(buffer) => buffer[start..].to_string(),
+        };
+        output
     }
 }
 
 #[derive(ClapParser)]
-#[command(version, author)]
+#[command(
+    version,
+    author,
+    override_usage = "clog [OPTIONS] [COMMAND] [FILES]..."
+)]
 struct Cli {
-    #[arg(value_enum, long="color", default_value_t=ColorChoice::Always)]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[arg(
+        global = true,
+        help = "Log files to read, after the command if there is one. Reads stdin when none are given"
+    )]
+    files: Vec<PathBuf>,
+
+    #[arg(value_enum, long="color", default_value_t=ColorChoice::Auto, global = true)]
     color: ColorChoice,
 
-    #[arg(short, long, help = "Extra values to print. Eg. X-CDP-SDK")]
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        help = "Colors the terminal can show. Detected from $COLORTERM and $TERM by default"
+    )]
+    color_depth: Option<ColorDepth>,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        help = "Colors to use, eg. light for light terminal backgrounds. Defaults to $CLOG_THEME or dark"
+    )]
+    theme: Option<ThemeName>,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Extra values to print. Eg. X-CDP-SDK"
+    )]
     extra: Vec<String>,
 
     #[arg(
         long,
+        global = true,
         help = "Turn on debug mode. All lines that can't be parsed will be output to stderr"
     )]
     debug: bool,
 
+    #[arg(
+        long,
+        global = true,
+        default_value_t = MAX_ENTRY_LINES,
+        value_parser = parse_limit,
+        help = "Lines an unfinished JSON entry may span before it's printed as text"
+    )]
+    max_entry_lines: usize,
+
+    #[arg(
+        long,
+        global = true,
+        default_value_t = MAX_ENTRY_BYTES,
+        value_parser = parse_limit,
+        help = "Bytes an unfinished JSON entry may take before it's printed as text"
+    )]
+    max_entry_bytes: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        global = true,
+        default_value_t = Encoding::Utf8,
+        help = "Encoding of the input. Invalid UTF-8 is shown as \u{FFFD}"
+    )]
+    encoding: Encoding,
+
     #[arg(short, long, help = "Show all additional info in a map")]
     verbose: bool,
 
+    #[arg(
+        long,
+        help = "With --verbose, print nested objects and arrays as inline JSON instead of trees"
+    )]
+    compact: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = FieldOrder::Sorted,
+        help = "Order of the fields printed with --verbose"
+    )]
+    field_order: FieldOrder,
+
     #[arg(
         short = 's',
         long,
@@ -466,65 +1070,436 @@ struct Cli {
     )]
     min_severity: Option<Severity>,
 
-    #[arg(long, help = "Output timestamps in UTC")]
+    #[arg(long, global = true, help = "Output timestamps in UTC")]
     utc: bool,
 
-    #[arg(long = "oneline")]
+    #[arg(
+        long,
+        global = true,
+        help = "Don't page the output of files through $CLOG_PAGER, $PAGER or less"
+    )]
+    no_pager: bool,
+
+    #[arg(long = "oneline", help = "Truncate each entry to a single line")]
     oneline: bool,
+
+    #[arg(
+        long,
+        default_value = "auto",
+        value_parser = parse_max_width,
+        help = "Width to wrap or truncate entries at: auto for the terminal width, or a number of columns"
+    )]
+    max_width: MaxWidth,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Indent entries under their tracing span path, showing each span once"
+    )]
+    tree: bool,
+
+    #[arg(
+        long,
+        help = "Print summary statistics when the input ends or on Ctrl-C"
+    )]
+    stats: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "exact",
+        global = true,
+        help = "Collapse consecutive entries with the same message and severity"
+    )]
+    dedup: Option<DedupMode>,
+
+    #[arg(
+        long,
+        value_name = "THREADS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0",
+        global = true,
+        requires = "files",
+        conflicts_with_all = ["tree", "dedup"],
+        help = "Parse files on several threads, by default one per CPU"
+    )]
+    parallel: Option<usize>,
 }
 
-#[derive(ClapValueEnum, Clone, Debug)]
-enum ColorChoice {
-    Auto,
-    Never,
-    Always,
+#[derive(ClapSubcommand)]
+enum Command {
+    #[command(about = "Print only summary statistics of the input")]
+    Stats,
+
+    #[command(about = "List all fields seen in the input with their types and frequencies")]
+    Fields,
+
+    #[command(about = "Print the most common messages, with numbers and ids masked")]
+    Top {
+        #[arg(
+            short = 'n',
+            long,
+            default_value_t = 10,
+            help = "Number of messages to print"
+        )]
+        limit: usize,
+    },
+
+    #[command(about = "Print a histogram of the number of entries over time per severity")]
+    Histogram {
+        #[arg(
+            long,
+            value_parser = parse_duration,
+            help = "Size of each time bucket, eg. 30s, 1m or 1h. Picked from the time span by default"
+        )]
+        bucket: Option<Duration>,
+    },
+
+    #[command(about = "Print count, min, max, mean and percentiles of a numeric field")]
+    Agg {
+        #[arg(long, help = "The numeric field to aggregate, eg. duration_ms")]
+        field: String,
+
+        #[arg(long, help = "Group the values by this field, eg. route")]
+        by: Option<String>,
+
+        #[arg(
+            long,
+            value_parser = parse_duration,
+            help = "Also group the values by time buckets of this size, eg. 1m"
+        )]
+        bucket: Option<Duration>,
+    },
+
+    #[command(about = "Browse the input in a full-screen viewer with filtering and search")]
+    Tui,
+}
+
+impl Command {
+    fn report(&self, config: &PrintConfig) -> Option<Box<dyn Report>> {
+        Some(match self {
+            Command::Stats => Box::new(Stats::new(&config.extra)),
+            Command::Fields => Box::new(FieldStats::default()),
+            Command::Top { limit } => Box::new(TopMessages::new(*limit)),
+            Command::Histogram { bucket } => {
+                Box::new(Histogram::new(*bucket, terminal_width(), config))
+            }
+            Command::Agg { field, by, bucket } => {
+                Box::new(Aggregation::new(field.clone(), by.clone(), *bucket, config))
+            }
+            Command::Tui => return None,
+        })
+    }
+}
+
+impl Cli {
+    /// Parses the arguments, and rejects files named like a command that don't
+    /// exist. They're a command given after the files, which clap would read
+    /// as one more file.
+    fn parse_args<I, T>(args: I) -> Result<Cli, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        use clap::CommandFactory;
+        let cli = Cli::try_parse_from(args)?;
+        let mut command = Cli::command();
+        let misplaced = cli
+            .files
+            .iter()
+            .find(|path| command.find_subcommand(path.as_os_str()).is_some() && !path.exists());
+        if let Some(path) = misplaced {
+            let name = path.display();
+            return Err(command.error(
+                clap::error::ErrorKind::ArgumentConflict,
+                format!(
+                    "`{name}` is a command, which goes before the files, eg. `clog {name} app.log`"
+                ),
+            ));
+        }
+        Ok(cli)
+    }
+}
+
+/// The most input that's read at once, and parsed together.
+const BLOCK_SIZE: usize = 64 << 10;
+
+/// Reads `reader` in blocks of whole lines, as soon as they arrive, and passes
+/// them to `send` until it returns false. Lines longer than `max_length` bytes
+/// are split, so that input without line breaks can't take all memory, and
+/// the parts are printed as text. Returns whether the whole input was read.
+fn read_blocks(
+    reader: &mut dyn std::io::Read,
+    max_length: usize,
+    send: &mut dyn FnMut(std::io::Result<Vec<u8>>) -> bool,
+) -> bool {
+    let mut block = Vec::with_capacity(BLOCK_SIZE);
+    loop {
+        let length = block.len();
+        block.resize(length + BLOCK_SIZE, 0);
+        let read = reader.read(&mut block[length..]);
+        block.truncate(length + *read.as_ref().unwrap_or(&0));
+        match read {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                send(Err(e));
+                return false;
+            }
+        }
+        // Only the new input can have a line break in it.
+        let mut end = block[length..]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |i| length + i + 1);
+        loop {
+            if end == 0 && block.len() > max_length {
+                end = max_length.max(1);
+            }
+            if end == 0 {
+                break;
+            }
+            let rest = block.split_off(end);
+            if !send(Ok(std::mem::replace(&mut block, rest))) {
+                return false;
+            }
+            end = 0;
+        }
+    }
+    block.is_empty() || send(Ok(block))
+}
+
+/// Reads the files one after another, or stdin if there are none, on a
+/// separate thread, so that held back entries can be printed while waiting for
+/// more input.
+fn read_input(files: Vec<File>, max_length: usize) -> Receiver<std::io::Result<Vec<u8>>> {
+    let (sender, blocks) = mpsc::sync_channel(16);
+    thread::spawn(move || {
+        let mut send = |block| sender.send(block).is_ok();
+        if files.is_empty() {
+            read_blocks(&mut std::io::stdin().lock(), max_length, &mut send);
+        }
+        for mut file in files {
+            if !read_blocks(&mut file, max_length, &mut send) {
+                break;
+            }
+        }
+    });
+    blocks
+}
+
+/// Whether `error` comes from writing to a pipe that was closed, eg. by
+/// `clog app.log | head`.
+fn is_broken_pipe(error: &eyre::Report) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe)
+    })
 }
 
 fn main() -> eyre::Result<()> {
+    match run() {
+        Err(e) if is_broken_pipe(&e) => Ok(()),
+        result => result,
+    }
+}
+
+fn run() -> eyre::Result<()> {
     use std::io::{self, prelude::*};
 
-    let args: Cli = Cli::parse();
-    match args.color {
-        ColorChoice::Always => colored::control::set_override(true),
-        ColorChoice::Never => colored::control::set_override(false),
-        _ => {}
-    };
+    let args = Cli::parse_args(std::env::args_os()).unwrap_or_else(|e| e.exit());
+    color::init(args.color, args.color_depth, io::stdout().is_terminal());
+    theme::init(args.theme)?;
 
     let print_config = PrintConfig {
         extra: args.extra,
         is_local_timezone: !args.utc,
         verbose: args.verbose,
-        oneline_maxlength: if args.oneline { Some(100) } else { None },
+        oneline: args.oneline,
+        compact: args.compact,
+        sort_fields: args.field_order == FieldOrder::Sorted,
+        max_width: match args.max_width {
+            MaxWidth::Auto if io::stdout().is_terminal() => Some(terminal_width()),
+            MaxWidth::Auto => None,
+            MaxWidth::Columns(columns) => Some(columns),
+        },
+        span_tree: args.tree,
+    };
+
+    let print_entries = args.command.is_none();
+    let mut reports: Vec<Box<dyn Report>> = vec![];
+    if let Some(command) = &args.command {
+        reports.extend(command.report(&print_config));
+    } else if args.stats {
+        reports.push(Box::new(Stats::new(&print_config.extra)));
+    }
+
+    let files = args
+        .files
+        .iter()
+        .map(|path| File::open(path).wrap_err_with(|| format!("Could not open {}", path.display())))
+        .collect::<Result<Vec<_>>>()?;
+
+    // Page the output when it's more than a screen of finite input. The pager
+    // also ends the input when it's quit.
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let use_pager = !args.no_pager
+        && !files.is_empty()
+        && io::stdout().is_terminal()
+        && !matches!(args.command, Some(Command::Tui));
+    let pager = if use_pager {
+        Pager::spawn(interrupted.clone())
+    } else {
+        None
     };
 
+    let mut printer = Printer::new(print_config);
+    printer.min_severity = args.min_severity;
+    printer.dedup = args.dedup.map(Dedup::new);
+
     let mut parser = Parser::new();
     parser.debug = args.debug;
+    parser.max_entry_lines = args.max_entry_lines;
+    parser.max_entry_bytes = args.max_entry_bytes;
+    parser.encoding = args.encoding;
+    parser.interrupted = interrupted.clone();
+
+    if let Some(Command::Tui) = args.command {
+        let blocks = read_input(files, args.max_entry_bytes);
+        return tui::run(printer.config, printer.min_severity, parser, blocks);
+    }
 
-    let mut stdout = io::stdout().lock();
-    for line in io::stdin().lock().lines() {
-        let mut unwrapped = line.unwrap().to_string();
-        unwrapped.push('\n');
-        let outputs = parser.push(&unwrapped);
-        for output in outputs {
-            match &args.min_severity {
-                Some(minimum) => {
-                    let is_included = match &output {
-                        ParserOutput::None => false,
-                        ParserOutput::Text(_) => true,
-                        ParserOutput::Log(m) => m.severity() >= *minimum,
-                    };
-                    if !is_included {
-                        continue;
-                    }
+    // Let Ctrl-C and SIGTERM end the input, so that what's buffered and the
+    // reports are still printed before exiting. A second signal exits
+    // immediately. Like git, Ctrl-C is left to the pager while it runs, since
+    // less uses it to stop searching or following, and the input ends when
+    // the pager is quit instead.
+    let signal = Arc::new(AtomicUsize::new(0));
+    let mut stop_signals = vec![SIGTERM];
+    if pager.is_some() {
+        for number in PAGER_SIGNALS {
+            signal_hook::flag::register(number, Arc::new(AtomicBool::new(false)))?;
+        }
+    } else {
+        stop_signals.push(SIGINT);
+    }
+    for number in stop_signals {
+        let code = 128 + number;
+        signal_hook::flag::register_conditional_shutdown(number, code, interrupted.clone())?;
+        signal_hook::flag::register(number, interrupted.clone())?;
+        signal_hook::flag::register_usize(number, signal.clone(), number as usize)?;
+    }
+
+    let mut out: Box<dyn Write> = match pager {
+        Some(pager) => Box::new(pager),
+        None => Box::new(io::BufWriter::new(io::stdout().lock())),
+    };
+    if let Some(jobs) = args.parallel {
+        let worker = parallel::Worker {
+            config: printer.config.clone(),
+            min_severity: printer.min_severity.clone(),
+            print_entries,
+            keep_outputs: !reports.is_empty(),
+            debug: args.debug,
+            max_entry_lines: args.max_entry_lines,
+            max_entry_bytes: args.max_entry_bytes,
+            encoding: args.encoding,
+        };
+        parallel::run(files, jobs, parallel::CHUNK_SIZE, worker, |chunk| {
+            parser.failures += chunk.failures;
+            for output in &chunk.outputs {
+                for report in reports.iter_mut() {
+                    report.add(output);
                 }
-                None => {}
             }
-            output.print(&mut stdout, &print_config)?;
-            stdout.flush()?;
+            out.write_all(&chunk.printed)?;
+            out.flush()?;
+            Ok(!interrupted.load(Ordering::SeqCst))
+        })?;
+    } else {
+        read_all(
+            read_input(files, args.max_entry_bytes),
+            &mut parser,
+            &mut printer,
+            &mut reports,
+            print_entries,
+            &mut out,
+            &interrupted,
+        )?;
+    }
+    for report in reports.iter() {
+        if print_entries {
+            writeln!(out)?;
         }
+        report.print(&mut out, &printer.config, &parser)?;
     }
-    parser.flush().print(&mut stdout, &print_config)?;
 
+    match signal.load(Ordering::SeqCst) {
+        0 => Ok(()),
+        number => {
+            drop(out);
+            std::process::exit(128 + number as i32)
+        }
+    }
+}
+
+/// Reads the input one line at a time, printing entries as soon as they are
+/// complete.
+fn read_all(
+    blocks: Receiver<std::io::Result<Vec<u8>>>,
+    parser: &mut Parser,
+    printer: &mut Printer,
+    reports: &mut [Box<dyn Report>],
+    print_entries: bool,
+    mut out: &mut dyn Write,
+    interrupted: &AtomicBool,
+) -> Result<()> {
+    loop {
+        if interrupted.load(Ordering::SeqCst) {
+            break;
+        }
+        // Wake up now and then while waiting for input, to notice signals.
+        let deadline = printer.deadline();
+        let wait = deadline.map_or(INTERRUPT_POLL, |deadline| {
+            deadline
+                .saturating_duration_since(Instant::now())
+                .min(INTERRUPT_POLL)
+        });
+        let block = match blocks.recv_timeout(wait) {
+            Ok(block) => block,
+            Err(RecvTimeoutError::Timeout) => {
+                if deadline.is_some_and(|deadline| deadline <= Instant::now()) {
+                    printer.flush(&mut out)?;
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let outputs = parser.push_bytes(block?);
+        for output in outputs {
+            for report in reports.iter_mut() {
+                report.add(&output);
+            }
+            if print_entries {
+                printer.push(&mut out, output)?;
+            }
+        }
+        // The output is written once per block of input, which is as soon as
+        // the lines arrive when following a stream.
+        out.flush()?;
+    }
+    let output = parser.flush();
+    for report in reports.iter_mut() {
+        report.add(&output);
+    }
+    if print_entries {
+        printer.push(&mut out, output)?;
+        printer.flush(&mut out)?;
+    }
     Ok(())
 }
 
@@ -534,15 +1509,11 @@ mod test {
     use pretty_assertions::assert_eq;
 
     impl ParserOutput {
+        #[allow(clippy::inherent_to_string)]
         fn to_string(&self) -> String {
-            let config = PrintConfig {
-                extra: vec![],
-                is_local_timezone: false,
-                verbose: false,
-                oneline_maxlength: None,
-            };
             let mut s = Vec::<u8>::new();
-            self.print(&mut s, &config).expect("Fail to write");
+            self.print(&mut s, &PrintConfig::default())
+                .expect("Fail to write");
             String::from_utf8(s).expect("Couldn't convert to string")
         }
     }
@@ -563,17 +1534,13 @@ mod test {
             "message": "Responding at http://0.0.0.0:8080",
             "context": {}
           }"#;
-        let lines: Vec<String> = input
-            .split('\n')
-            .into_iter()
-            .map(|it| it.to_owned() + "\n")
-            .collect();
+        let lines: Vec<String> = input.split('\n').map(|it| it.to_owned() + "\n").collect();
         let mut parser = Parser::new();
 
         // Add all but the last line. It is only after the list line that the
         // log statement is complete.
-        for i in 0..lines.len() - 1 {
-            let response = parser.push(&lines[i]);
+        for line in &lines[..lines.len() - 1] {
+            let response = parser.push(line);
             assert_eq!(0, response.len());
         }
 
@@ -595,17 +1562,13 @@ mod test {
               "processId": "776f2d01-8bba-4c36-b6a8-5f7074c096d7"
             }
           }"#;
-        let lines: Vec<String> = input
-            .split('\n')
-            .into_iter()
-            .map(|it| it.to_owned() + "\n")
-            .collect();
+        let lines: Vec<String> = input.split('\n').map(|it| it.to_owned() + "\n").collect();
         let mut parser = Parser::new();
 
         // Add all but the last line. It is only after the list line that the
         // log statement is complete.
-        for i in 0..lines.len() - 1 {
-            let response = parser.push(&lines[i]);
+        for line in &lines[..lines.len() - 1] {
+            let response = parser.push(line);
             assert_eq!(0, response.len());
         }
         let output = parser.push(&lines[lines.len() - 1])[0].to_string();
@@ -640,9 +1603,7 @@ mod test {
                 &mut buffer,
                 &PrintConfig {
                     extra: vec!["reportLocation.modulePath".to_string()],
-                    verbose: false,
-                    is_local_timezone: false,
-                    oneline_maxlength: None,
+                    ..Default::default()
                 },
             )
             .unwrap();
@@ -686,9 +1647,7 @@ mod test {
                 &mut buffer,
                 &PrintConfig {
                     extra: vec!["callsite.module".to_string()],
-                    verbose: false,
-                    is_local_timezone: false,
-                    oneline_maxlength: None,
+                    ..Default::default()
                 },
             )
             .unwrap();
@@ -699,8 +1658,7 @@ mod test {
         );
     }
 
-    #[test]
-    fn with_verbose_printing() {
+    fn print_verbose(sort_fields: bool) -> String {
         let input = r#"{
             "event": "Refreshing project usage data",
             "@timestamp": "2023-09-14T12:39:35.604694Z",
@@ -733,12 +1691,17 @@ mod test {
                 &PrintConfig {
                     extra: vec!["callsite.module".to_string()],
                     verbose: true,
-                    is_local_timezone: false,
-                    oneline_maxlength: None,
+                    sort_fields,
+                    ..Default::default()
                 },
             )
             .unwrap();
         }
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn with_verbose_printing() {
         let expected = r#"2023-09-14 12:39:35.604Z [bookkeeper] DEBUG   Refreshing project usage data
   callsite:
     filename = bookkeeper.py
@@ -746,7 +1709,7 @@ mod test {
     lineno = 116
     module = bookkeeper
     my_bool = true
-    my_list = [Num(1.0), Num(2.0), Num(3.0)]
+    my_list = [1, 2, 3]
     pathname = /app/okkeeper.py
     thread = 140450880908160
     thread_name = MainThread
@@ -754,8 +1717,61 @@ mod test {
     connection_wait_time_ms = 0
     sql_statement_hash = abc123
 "#;
-        let buffer_str = String::from_utf8(buffer).unwrap();
-        assert_eq!(&buffer_str, expected,);
+        assert_eq!(print_verbose(true), expected);
+    }
+
+    #[test]
+    fn with_verbose_printing_in_original_order() {
+        let expected = r#"2023-09-14 12:39:35.604Z [bookkeeper] DEBUG   Refreshing project usage data
+  db:
+    connection_wait_time_ms = 0
+    sql_statement_hash = abc123
+  callsite:
+    pathname = /app/okkeeper.py
+    filename = bookkeeper.py
+    module = bookkeeper
+    func_name = _keep_refreshing_usage_data
+    lineno = 116
+    my_bool = true
+    my_list = [1, 2, 3]
+    thread = 140450880908160
+    thread_name = MainThread
+"#;
+        assert_eq!(print_verbose(false), expected);
+    }
+
+    #[test]
+    fn extra_values_in_field_order() {
+        before();
+        let input =
+            r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z", "ctx": {"b": 1, "a": 2}}"#;
+        let output = Parser::new().push(input).remove(0);
+        let print = |sort_fields| {
+            let config = PrintConfig {
+                extra: vec!["ctx".to_string()],
+                sort_fields,
+                ..Default::default()
+            };
+            let mut buffer = Vec::new();
+            output.print(&mut buffer, &config).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+        assert_eq!(
+            "2022-04-01 18:49:52.000Z [{\"a\": 2, \"b\": 1}] UNKNOWN a\n",
+            print(true)
+        );
+        assert_eq!(
+            "2022-04-01 18:49:52.000Z [{\"b\": 1, \"a\": 2}] UNKNOWN a\n",
+            print(false)
+        );
+    }
+
+    #[test]
+    fn defaults_to_sorted_fields() {
+        let args = Cli::parse_from(["clog"]);
+        assert_eq!(FieldOrder::Sorted, args.field_order);
+        let args = Cli::parse_from(["clog", "--field-order", "original"]);
+        assert_eq!(FieldOrder::Original, args.field_order);
     }
 
     #[test]
@@ -791,5 +1807,347 @@ mod test {
             ParserOutput::Text("Hello world".to_string()).to_string(),
             parser.push("Hello world")[0].to_string()
         );
+
+        let input = "{\"message\": \"bad \\q escape\"}\n";
+        assert!(matches!(&parser.push(input)[..], [ParserOutput::Text(text)] if text == input));
+        assert_eq!(1, parser.failures);
+        assert!(matches!(
+            parser.push(r#"{"message": "ok \/", "timestamp": "2022-04-01T18:49:52Z"}"#)[0],
+            ParserOutput::Log(_)
+        ));
+    }
+
+    #[test]
+    fn parses_incrementally() {
+        before();
+        let mut parser = Parser::new();
+        let entry = r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z"}"#;
+        let outputs = parser.push(&format!("{}\n", entry.repeat(20_000)));
+        assert_eq!(20_000, outputs.len());
+        assert!(outputs.iter().all(|o| matches!(o, ParserOutput::Log(_))));
+
+        assert!(parser.push("{\"message\": \"b\",\n").is_empty());
+        for i in 0..5_000 {
+            assert!(parser
+                .push(&format!("  \"field{}\": [{}],\n", i, i))
+                .is_empty());
+        }
+        let outputs = parser.push("  \"timestamp\": \"2022-04-01T18:49:52Z\"}\n");
+        match &outputs[..] {
+            [ParserOutput::Log(line)] => assert_eq!(5_002, line.fields().len()),
+            outputs => panic!("Not a log line: {:?}", outputs),
+        }
+        assert!(matches!(parser.flush(), ParserOutput::None));
+    }
+
+    #[test]
+    fn resyncs_after_unfinished_entry() {
+        before();
+        let mut parser = Parser::new();
+        assert!(parser.push("{\"message\": \"truncat\n").is_empty());
+        let entry = r#"{"message": "b", "timestamp": "2022-04-01T18:49:52Z"}"#;
+        match &parser.push(&format!("{}\n", entry))[..] {
+            [ParserOutput::Text(text), ParserOutput::Log(line)] => {
+                assert_eq!("{\"message\": \"truncat\n", text);
+                assert_eq!(Some("b".to_string()), line.value("message"));
+            }
+            outputs => panic!("Not resynced: {:?}", outputs),
+        }
+        assert_eq!(1, parser.failures);
+
+        // Without anything invalid, the entry is given up on at the limits.
+        parser.max_entry_lines = 3;
+        assert!(parser.push("{\"message\": \"never closed\n").is_empty());
+        assert!(parser.push("text\n").is_empty());
+        let outputs = parser.push("more text\n");
+        assert!(
+            matches!(&outputs[..], [ParserOutput::Text(text)] if text.ends_with("more text\n"))
+        );
+        parser.max_entry_bytes = 20;
+        let outputs = parser.push("{\"message\": \"this is too long\n");
+        assert!(matches!(&outputs[..], [ParserOutput::Text(_)]));
+        assert!(matches!(
+            &parser.push("text\n")[..],
+            [ParserOutput::Text(_)]
+        ));
+        assert_eq!(3, parser.failures);
+    }
+
+    #[test]
+    fn stops_at_smallest_entry_limits() {
+        before();
+        let entry = "{\"message\": \"a\", \"timestamp\": \"2022-04-01T18:49:52Z\"}\n";
+        for limit in [0, 1] {
+            let mut parser = Parser::new();
+            parser.max_entry_lines = limit;
+            parser.max_entry_bytes = limit;
+            assert!(matches!(&parser.push(entry)[..], [ParserOutput::Log(_)]));
+            let outputs = parser.push("{\"message\": \"b\",\n");
+            assert!(
+                matches!(&outputs[..], [ParserOutput::Text(text)] if text == "{\"message\": \"b\",\n")
+            );
+            assert!(matches!(&parser.push(entry)[..], [ParserOutput::Log(_)]));
+            assert!(matches!(parser.flush(), ParserOutput::None));
+        }
+        assert!(Cli::try_parse_from(["clog", "--max-entry-lines", "0"]).is_err());
+    }
+
+    #[test]
+    fn reads_blocks_of_lines() {
+        use std::io::Read;
+
+        let mut input = b"abcdefg".chain(&b"h\nab"[..]).chain(&b"cd"[..]);
+        let mut blocks = vec![];
+        let mut send = |block: std::io::Result<Vec<u8>>| {
+            blocks.push(String::from_utf8(block.unwrap()).unwrap());
+            true
+        };
+        assert!(read_blocks(&mut input, 3, &mut send));
+        assert_eq!(vec!["abc", "def", "gh\n", "abc", "d"], blocks);
+    }
+
+    #[test]
+    fn reads_entries_of_many_lines() {
+        before();
+        let mut parser = Parser::new();
+        let mut outputs = parser.push("{\n  \"message\": \"big\",\n");
+        for i in 0..20_000 {
+            outputs.extend(parser.push(&format!("  \"key{i}\": {i},\n")));
+        }
+        outputs.extend(parser.push("  \"timestamp\": \"2022-04-01T18:49:52Z\"\n}\n"));
+        match &outputs[..] {
+            [ParserOutput::Log(line)] => {
+                assert_eq!(Some("19999".to_string()), line.value("key19999"))
+            }
+            outputs => panic!("Not one log line: {:?}", outputs.len()),
+        }
+        assert_eq!(0, parser.failures);
+    }
+
+    #[test]
+    fn stops_parsing_when_interrupted() {
+        before();
+        let mut parser = Parser::new();
+        parser.interrupted.store(true, Ordering::SeqCst);
+        let input = "{\"message\": \"a\", \"timestamp\": \"2022-04-01T18:49:52Z\"}\n";
+        assert!(parser.push(input).is_empty());
+        assert!(matches!(parser.flush(), ParserOutput::Text(text) if text == input));
+    }
+
+    #[test]
+    fn reads_invalid_bytes() {
+        before();
+        let input = b"{\"message\": \"caf\xe9\", \"timestamp\": \"2022-04-01T18:49:52Z\"}";
+        let message = |parser: &mut Parser| match &parser.push_bytes(input.to_vec())[..] {
+            [ParserOutput::Log(line)] => line.value("message"),
+            outputs => panic!("Not a log line: {:?}", outputs),
+        };
+        let mut parser = Parser::new();
+        assert_eq!(Some("caf\u{FFFD}".to_string()), message(&mut parser));
+        assert!(matches!(
+            &parser.push_bytes(b"\xff\xfe binary".to_vec())[..],
+            [ParserOutput::Text(text)] if text == "\u{FFFD}\u{FFFD} binary\n"
+        ));
+        parser.encoding = Encoding::Latin1;
+        assert_eq!(Some("café".to_string()), message(&mut parser));
+    }
+
+    #[test]
+    fn reads_json_after_prefix() {
+        before();
+        let mut parser = Parser::new();
+        let input = concat!(
+            "2023-10-01T12:00:00Z stdout F {\"level\": \"info\", \"msg\": \"started\"}\n",
+            "web-1  | {\"message\": \"ready\", \"time\": \"2022-04-01T18:49:52Z\"}\n",
+            "web-1  | {\"message\": \"no time\"}\n",
+        );
+        let outputs = parser.push(input);
+        assert_eq!(
+            vec![
+                "2023-10-01 12:00:00.000Z INFO    started\n",
+                "2022-04-01 18:49:52.000Z [web-1] UNKNOWN ready\n",
+                "web-1  | {\"message\": \"no time\"}\n",
+            ],
+            outputs.iter().map(|o| o.to_string()).collect::<Vec<_>>()
+        );
+        match &outputs[0] {
+            ParserOutput::Log(line) => {
+                assert_eq!(Some("stdout".to_string()), line.value("stream"));
+                assert!(line.fields().contains_key("stream"));
+            }
+            output => panic!("Not a log line: {:?}", output),
+        }
+    }
+
+    #[test]
+    fn reads_prefixed_lines_after_blank_lines() {
+        before();
+        let mut parser = Parser::new();
+        let mut outputs = vec![];
+        let input = "web-1  | {\"message\":\"a\",\"ts\":\"2022-04-01T18:49:52Z\"}\n\n  \nweb-1  | {\"message\":\"b\",\"ts\":\"2022-04-01T18:49:52Z\"}\n";
+        for line in input.split_inclusive('\n') {
+            outputs.extend(parser.push(line));
+        }
+        let messages: Vec<_> = outputs
+            .iter()
+            .filter_map(|output| match output {
+                ParserOutput::Log(line) => line.value("message"),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["a", "b"], messages);
+    }
+
+    #[test]
+    fn joins_cri_partial_lines() {
+        before();
+        let mut parser = Parser::new();
+        assert!(parser
+            .push("2023-10-01T12:00:00Z stdout P {\"level\": \"info\", \"msg\": \"lo\n")
+            .is_empty());
+        assert!(parser
+            .push("2023-10-01T12:00:01Z stderr F plain text\n")
+            .iter()
+            .all(|output| matches!(output, ParserOutput::Text(_))));
+        assert!(parser
+            .push("2023-10-01T12:00:01Z stdout P ng \n")
+            .is_empty());
+        assert_eq!(
+            "2023-10-01 12:00:02.000Z INFO    long line\n",
+            parser.push("2023-10-01T12:00:02Z stdout F line\"}\n")[0].to_string()
+        );
+
+        assert!(parser
+            .push("2023-10-01T12:00:00Z stdout P unfinished\n")
+            .is_empty());
+        assert!(matches!(parser.flush(), ParserOutput::Text(text) if text == "unfinished\n"));
+    }
+
+    #[test]
+    fn unwraps_docker_records() {
+        before();
+        let mut parser = Parser::new();
+        let record = |log: &str, stream| {
+            let log = to_json(&JsonValue::Str(log.into()), true);
+            format!(
+                "{{\"log\":{},\"stream\":\"{}\",\"time\":\"2023-10-01T12:00:00.5Z\"}}\n",
+                log, stream
+            )
+        };
+        let input = [
+            record("{\"level\":\"info\",\"msg\":\"ready\"}\n", "stdout"),
+            record("{\"level\":\"error\",\"msg\":\"long ", "stderr"),
+            record("plain text\n", "stdout"),
+            record("line\"}\n", "stderr"),
+        ]
+        .concat();
+        let outputs = parser.push(&input);
+        assert_eq!(
+            vec![
+                "2023-10-01 12:00:00.500Z INFO    ready\n",
+                "plain text\n",
+                "2023-10-01 12:00:00.500Z ERROR   long line\n",
+            ],
+            outputs.iter().map(|o| o.to_string()).collect::<Vec<_>>()
+        );
+        match &outputs[2] {
+            ParserOutput::Log(line) => assert_eq!(Some("stderr".to_string()), line.value("stream")),
+            output => panic!("Not a log line: {:?}", output),
+        }
+
+        assert!(parser.push(&record("unfinished", "stdout")).is_empty());
+        assert!(matches!(parser.flush(), ParserOutput::Text(text) if text == "unfinished\n"));
+
+        // The attrs tell the lines of containers apart, and are fields of
+        // their entries.
+        let with_tag = |log, tag| {
+            let attrs = format!(",\"attrs\":{{\"tag\":\"{tag}\"}}}}\n");
+            record(log, "stdout").replace("}\n", &attrs)
+        };
+        let input = [
+            with_tag("{\"msg\":\"from ", "web"),
+            with_tag("{\"msg\":\"from ", "db"),
+            with_tag("db\"}\n", "db"),
+            with_tag("web\"}\n", "web"),
+        ]
+        .concat();
+        let outputs = parser.push(&input);
+        assert_eq!(
+            vec![
+                "2023-10-01 12:00:00.500Z UNKNOWN from db\n",
+                "2023-10-01 12:00:00.500Z UNKNOWN from web\n",
+            ],
+            outputs.iter().map(|o| o.to_string()).collect::<Vec<_>>()
+        );
+        match &outputs[0] {
+            ParserOutput::Log(line) => assert_eq!(Some("db".to_string()), line.value("tag")),
+            output => panic!("Not a log line: {:?}", output),
+        }
+        // Docker's attrs are only strings, so this is an entry of its own.
+        let with_other_attrs = record("plain text\n", "stdout").replace('}', ",\"attrs\":[]}");
+        assert!(matches!(
+            &parser.push(&with_other_attrs)[..],
+            [ParserOutput::Log(line)] if line.value("log").as_deref() == Some("plain text\n")
+        ));
+        let entry = record("plain text\n", "stdout").replace('}', ",\"level\":\"warn\"}");
+        match &parser.push(&entry)[..] {
+            [ParserOutput::Log(line)] => assert_eq!(Some("warn".to_string()), line.value("level")),
+            outputs => panic!("Not a log line: {:?}", outputs),
+        }
+    }
+
+    #[test]
+    fn verify_cli() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn commands_go_before_files() {
+        let args = Cli::parse_args(["clog", "stats", "app.log"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Stats)));
+        assert_eq!(vec![PathBuf::from("app.log")], args.files);
+
+        let error = Cli::parse_args(["clog", "app.log", "stats"]).err().unwrap();
+        assert!(
+            error.to_string().contains("`clog stats app.log`"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn ignores_broken_pipe() {
+        let error = |kind| eyre::Report::new(std::io::Error::from(kind)).wrap_err("Writing");
+        assert!(is_broken_pipe(&error(std::io::ErrorKind::BrokenPipe)));
+        assert!(!is_broken_pipe(&error(std::io::ErrorKind::NotFound)));
+    }
+
+    #[test]
+    fn wraps_and_truncates_to_width() {
+        before();
+        let input = r#"{"message": "Failed to connect to the database at db.internal after three retries\nCaused by: timeout", "timestamp": "2022-04-01T18:49:52Z", "severity": "ERROR"}"#;
+        let output = Parser::new().push(input).remove(0);
+        let print = |oneline, max_width| {
+            let config = PrintConfig {
+                oneline,
+                max_width,
+                ..Default::default()
+            };
+            let mut buffer = Vec::new();
+            output.print(&mut buffer, &config).unwrap();
+            String::from_utf8(buffer).unwrap()
+        };
+
+        let expected = r#"2022-04-01 18:49:52.000Z ERROR   Failed to connect to the database at
+                                 db.internal after three retries
+                                 Caused by: timeout
+"#;
+        assert_eq!(expected, print(false, Some(70)));
+        let expected = "2022-04-01 18:49:52.000Z ERROR   Failed to connect to the database ...\n";
+        assert_eq!(expected, print(true, Some(70)));
+
+        assert_eq!(vec!["abcd", "efgh", "ij"], wrap("abcdefghij", 4));
+        assert_eq!(vec!["  ab", "cd ef"], wrap("  ab cd ef", 5));
     }
 }
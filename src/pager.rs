@@ -0,0 +1,118 @@
+use std::env;
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+const DEFAULT_PAGER: &str = "less -RFX";
+
+/// The pager command line, from `$CLOG_PAGER`, `$PAGER` or the default. None
+/// if paging is turned off with an empty value or `cat`.
+fn pager_command(clog_pager: Option<String>, pager: Option<String>) -> Option<String> {
+    let command = clog_pager
+        .or(pager)
+        .unwrap_or_else(|| DEFAULT_PAGER.to_string());
+    match command.trim() {
+        "" | "cat" => None,
+        command => Some(command.to_string()),
+    }
+}
+
+/// Runs the command line with the shell, like git does, so that it can quote
+/// paths and arguments.
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// Output piped through a pager. When the pager quits before all output is
+/// written, the rest is dropped and `closed` is set, so that reading the input
+/// can stop.
+pub struct Pager {
+    child: Child,
+    input: Option<BufWriter<ChildStdin>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl Pager {
+    /// Starts the pager, or returns None if it's turned off or can't be
+    /// started.
+    pub fn spawn(closed: Arc<AtomicBool>) -> Option<Self> {
+        let pager = pager_command(env::var("CLOG_PAGER").ok(), env::var("PAGER").ok())?;
+        let mut command = shell(&pager);
+        command.stdin(Stdio::piped());
+        // Like git, make a plain `less` show colors and quit on short output.
+        if env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        let mut child = command.spawn().ok()?;
+        let input = child.stdin.take().map(BufWriter::new);
+        Some(Pager {
+            child,
+            input,
+            closed,
+        })
+    }
+
+    fn check(&mut self, result: io::Result<()>) -> io::Result<()> {
+        match result {
+            Err(e) if e.kind() == ErrorKind::BrokenPipe => {
+                self.input = None;
+                self.closed.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = match &mut self.input {
+            Some(input) => input.write_all(buf),
+            None => Ok(()),
+        };
+        self.check(result).map(|_| buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let result = match &mut self.input {
+            Some(input) => input.flush(),
+            None => Ok(()),
+        };
+        self.check(result)
+    }
+}
+
+impl Drop for Pager {
+    /// Closes the pager's input and waits for the user to quit it.
+    fn drop(&mut self) {
+        drop(self.input.take());
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn picks_pager_command() {
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(some("less -RFX"), pager_command(None, None));
+        assert_eq!(some("most"), pager_command(some(" most "), some("less")));
+        assert_eq!(some("more"), pager_command(None, some("more")));
+        assert_eq!(None, pager_command(some(""), some("less")));
+        assert_eq!(None, pager_command(None, some("cat")));
+    }
+
+    #[test]
+    fn runs_pager_with_the_shell() {
+        let output = shell(r#"printf '%s|' "two words" 'it''s'"#)
+            .output()
+            .unwrap();
+        assert_eq!("two words|its|", String::from_utf8(output.stdout).unwrap());
+    }
+}
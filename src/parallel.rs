@@ -0,0 +1,304 @@
+use std::io::{self, ErrorKind, Read};
+use std::mem::take;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::docker::Record;
+use crate::parser::{Encoding, Scan, Scanner};
+use crate::prefix::find_prefix;
+use crate::{Parser, ParserOutput, PrintConfig, Printer, Severity};
+
+/// The size of the chunks files are split into.
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/// What a worker made of a chunk of input.
+#[derive(Default)]
+pub struct Chunk {
+    /// The printed entries.
+    pub printed: Vec<u8>,
+    /// The parser outputs, when they are kept for reports.
+    pub outputs: Vec<ParserOutput>,
+    pub failures: usize,
+}
+
+/// How the workers parse and print each chunk.
+#[derive(Clone)]
+pub struct Worker {
+    pub config: PrintConfig,
+    pub min_severity: Option<Severity>,
+    pub print_entries: bool,
+    pub keep_outputs: bool,
+    pub debug: bool,
+    pub max_entry_lines: usize,
+    pub max_entry_bytes: usize,
+    pub encoding: Encoding,
+}
+
+impl Worker {
+    fn process(&self, text: Vec<u8>) -> io::Result<Chunk> {
+        let mut parser = Parser::new();
+        parser.debug = self.debug;
+        parser.max_entry_lines = self.max_entry_lines;
+        parser.max_entry_bytes = self.max_entry_bytes;
+        parser.encoding = self.encoding;
+        let mut printer = Printer::new(self.config.clone());
+        printer.min_severity = self.min_severity.clone();
+
+        let mut chunk = Chunk::default();
+        let mut add = |output: ParserOutput| -> io::Result<()> {
+            if self.print_entries && printer.shows(&output) {
+                printer.print(&mut chunk.printed, &output)?;
+            }
+            if self.keep_outputs && !matches!(output, ParserOutput::None) {
+                chunk.outputs.push(output);
+            }
+            Ok(())
+        };
+        for output in parser.push_bytes(text) {
+            add(output)?;
+        }
+        // An entry that isn't finished at the end of a chunk is printed as
+        // text, like at the end of the input.
+        add(parser.flush())?;
+        chunk.failures = parser.failures;
+        Ok(chunk)
+    }
+}
+
+/// Whether the last line of `text` is split over several lines by Docker or a
+/// CRI runtime, and continues on a later line.
+fn ends_partial(text: &[u8]) -> bool {
+    let text = text.strip_suffix(b"\n").unwrap_or(text);
+    let start = text.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let line = String::from_utf8_lossy(&text[start..]);
+    let line = line.strip_suffix('\r').unwrap_or(&line);
+    if let Some(prefix) = find_prefix(line) {
+        return prefix.partial;
+    }
+    let mut scanner = Scanner::default();
+    match scanner.scan(line) {
+        Scan::Complete(end) if line[end..].trim().is_empty() => {
+            Record::read(&scanner.into_object(&line[..end]))
+                .is_some_and(|record| !record.log.ends_with('\n'))
+        }
+        _ => false,
+    }
+}
+
+/// Where to split `buffer`: before the last line that starts an object, so
+/// that entries written over several lines aren't split, or else after the
+/// last line. Never right after a partial line, so that the lines it was
+/// split into stay in one chunk.
+fn split_point(buffer: &[u8]) -> Option<usize> {
+    let line_starts = || {
+        let newlines = buffer.iter().enumerate().rev().filter(|(_, &b)| b == b'\n');
+        newlines.map(|(i, _)| i + 1)
+    };
+    let after_whole_line = |split: &usize| !ends_partial(&buffer[..*split]);
+    line_starts()
+        .filter(|&i| buffer.get(i) == Some(&b'{'))
+        .find(after_whole_line)
+        .or_else(|| line_starts().find(after_whole_line))
+}
+
+/// Reads each of `readers` in chunks of whole lines of about `chunk_size`
+/// bytes, until `send` returns false. Where there's no line to split at for
+/// `max_length` bytes, the chunk is cut there, like long lines are when not
+/// parallel.
+fn read_chunks<R: Read>(
+    readers: Vec<R>,
+    chunk_size: usize,
+    max_length: usize,
+    mut send: impl FnMut(Vec<u8>) -> bool,
+) -> io::Result<()> {
+    for mut reader in readers {
+        let mut buffer = Vec::with_capacity(2 * chunk_size);
+        loop {
+            let length = buffer.len();
+            buffer.resize(length + chunk_size, 0);
+            let read = reader.read(&mut buffer[length..]);
+            buffer.truncate(length + *read.as_ref().unwrap_or(&0));
+            match read {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            if buffer.len() < chunk_size {
+                continue;
+            }
+            let split = match split_point(&buffer) {
+                Some(split) => Some(split),
+                None if buffer.len() > max_length => {
+                    buffer.push(b'\n');
+                    Some(buffer.len())
+                }
+                None => None,
+            };
+            if let Some(split) = split {
+                let rest = buffer.split_off(split);
+                if !send(std::mem::replace(&mut buffer, rest)) {
+                    return Ok(());
+                }
+            }
+        }
+        if !buffer.is_empty() {
+            if buffer.last() != Some(&b'\n') {
+                buffer.push(b'\n');
+            }
+            if !send(take(&mut buffer)) {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads `readers` in chunks, parses and prints the chunks with `jobs`
+/// threads, or one per CPU if it's 0, and passes them to `handle` in the
+/// order they were read, until it returns false.
+pub fn run<R>(
+    readers: Vec<R>,
+    jobs: usize,
+    chunk_size: usize,
+    worker: Worker,
+    mut handle: impl FnMut(Chunk) -> io::Result<bool>,
+) -> io::Result<()>
+where
+    R: Read + Send + 'static,
+{
+    let jobs = match jobs {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        jobs => jobs,
+    };
+
+    // Each chunk comes with a channel for its result, and the receiving ends
+    // are queued in the order the chunks were read.
+    type Task = (Vec<u8>, SyncSender<io::Result<Chunk>>);
+    let (tasks, task_receiver) = mpsc::sync_channel::<Task>(jobs);
+    let (results, ordered_results) = mpsc::sync_channel::<io::Result<Receiver<_>>>(2 * jobs);
+
+    let task_receiver = Arc::new(Mutex::new(task_receiver));
+    for _ in 0..jobs {
+        let task_receiver = task_receiver.clone();
+        let worker = worker.clone();
+        thread::spawn(move || loop {
+            let task = task_receiver.lock().unwrap().recv();
+            let (text, result) = match task {
+                Ok(task) => task,
+                Err(_) => break,
+            };
+            let _ = result.send(worker.process(text));
+        });
+    }
+
+    let max_length = worker.max_entry_bytes;
+    thread::spawn(move || {
+        let read = read_chunks(readers, chunk_size, max_length, |text| {
+            let (sender, receiver) = mpsc::sync_channel(1);
+            tasks.send((text, sender)).is_ok() && results.send(Ok(receiver)).is_ok()
+        });
+        if let Err(e) = read {
+            let _ = results.send(Err(e));
+        }
+    });
+
+    for result in ordered_results {
+        let chunk = result?
+            .recv()
+            .map_err(|_| io::Error::other("A worker thread stopped"))??;
+        if !handle(chunk)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn splits_between_entries() {
+        assert_eq!(Some(6), split_point(b"{\"a\"}\n{\"b\":\n  1}\n"));
+        assert_eq!(Some(9), split_point(b"text\n  1\n  2"));
+        assert_eq!(None, split_point(b"{\"a\": 1"));
+
+        let partial =
+            b"{\"log\":\"{\\\"a\\\":\",\"stream\":\"stdout\",\"time\":\"2023-10-01T12:00:00Z\"}\n";
+        let last = b"{\"log\":\"1}\\n\",\"stream\":\"stdout\",\"time\":\"2023-10-01T12:00:00Z\"}\n";
+        let records = [&last[..], partial, partial, last].concat();
+        assert_eq!(Some(last.len()), split_point(&records));
+        assert_eq!(None, split_point(&[&partial[..], partial].concat()));
+
+        let cri = b"2023-10-01T12:00:00Z stdout F {}\n2023-10-01T12:00:00Z stdout P {\n2023-10-01T12:00:00Z stdout F }\n";
+        assert_eq!(Some(cri.len()), split_point(cri));
+        assert_eq!(Some(33), split_point(&cri[..cri.len() - 32]));
+    }
+
+    #[test]
+    fn cuts_chunks_without_line_breaks() {
+        let mut chunks = vec![];
+        read_chunks(vec![&b"abcdefgh"[..]], 2, 4, |chunk| {
+            chunks.push(String::from_utf8(chunk).unwrap());
+            true
+        })
+        .unwrap();
+        assert_eq!(vec!["abcdef\n", "gh\n"], chunks);
+    }
+
+    #[test]
+    fn prints_chunks_in_order() {
+        colored::control::set_override(false);
+        let mut input = String::new();
+        for i in 0..50 {
+            input.push_str(&format!(
+                "{{\"message\": \"m{i}\", \"timestamp\": \"2022-04-01T18:49:52Z\"}}\n"
+            ));
+            if i % 10 == 0 {
+                input.push_str(
+                    "{\n  \"message\": \"pretty\",\n  \"timestamp\": \"2022-04-01T18:49:52Z\"\n}\n",
+                );
+                input.push_str("text\n{\"message\": invalid}\n");
+            }
+            if i % 10 == 5 {
+                for log in ["{\\\"message\\\": ", "\\\"long\\\", ", "\\\"n\\\": 1}\\n"] {
+                    input.push_str(&format!(
+                        "{{\"log\":\"{log}\",\"stream\":\"stdout\",\"time\":\"2022-04-01T18:49:52Z\"}}\n"
+                    ));
+                }
+            }
+        }
+        let input: &'static str = Box::leak(input.into_boxed_str());
+        let worker = Worker {
+            config: PrintConfig::default(),
+            min_severity: None,
+            print_entries: true,
+            keep_outputs: true,
+            debug: false,
+            max_entry_lines: 100,
+            max_entry_bytes: 1000,
+            encoding: Encoding::Utf8,
+        };
+
+        let mut expected = worker.process(input.as_bytes().to_vec()).unwrap();
+        assert_eq!(5, expected.failures);
+        let mut printed = vec![];
+        let mut outputs = 0;
+        let mut failures = 0;
+        run(vec![input.as_bytes()], 3, 100, worker, |mut chunk| {
+            printed.append(&mut chunk.printed);
+            outputs += chunk.outputs.len();
+            failures += chunk.failures;
+            Ok(true)
+        })
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(take(&mut expected.printed)).unwrap(),
+            String::from_utf8(printed).unwrap()
+        );
+        assert_eq!((expected.outputs.len(), 5), (outputs, failures));
+    }
+}
@@ -1,12 +1,17 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::Range;
 use std::str;
+use std::sync::Arc;
 
+use clap::ValueEnum as ClapValueEnum;
 use eyre::{eyre, Result};
+use indexmap::IndexMap;
 use nom::{
     branch::alt,
     bytes::streaming::{tag, take_while},
     character::streaming::char,
-    combinator::{cut, map, value},
+    combinator::{cut, map, recognize, value},
     error::{Error, ErrorKind, ParseError},
     multi::separated_list0,
     number::streaming::double,
@@ -14,18 +19,85 @@ use nom::{
     Err, IResult, Needed,
 };
 
+/// How the bytes of the input are read as text.
+#[derive(ClapValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    /// UTF-8, with invalid bytes shown as �.
+    #[default]
+    Utf8,
+    /// ISO 8859-1, which some legacy services write.
+    Latin1,
+}
+
+impl Encoding {
+    pub fn decode(self, bytes: &[u8]) -> Cow<'_, str> {
+        match self {
+            Encoding::Latin1 if !bytes.is_ascii() => bytes.iter().map(|&b| b as char).collect(),
+            _ => String::from_utf8_lossy(bytes),
+        }
+    }
+
+    /// Like [`Encoding::decode`], but without copying input that's valid
+    /// UTF-8.
+    pub fn decode_owned(self, bytes: Vec<u8>) -> String {
+        match self {
+            Encoding::Latin1 if !bytes.is_ascii() => self.decode(&bytes).into_owned(),
+            _ => String::from_utf8(bytes)
+                .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
+        }
+    }
+}
+
+/// The fields of an object, in the order they appear in the input.
+pub type Map<'a> = IndexMap<Cow<'a, str>, JsonValue<'a>>;
+
+/// A number as it was written in the input, so that ids and timestamps beyond
+/// the precision of `f64` are printed exactly.
 #[derive(Debug, PartialEq, Clone)]
-pub enum JsonValue {
-    Str(String),
+pub struct Number<'a>(Cow<'a, str>);
+
+impl Number<'_> {
+    pub fn as_f64(&self) -> f64 {
+        self.0.parse().unwrap_or(f64::NAN)
+    }
+
+    /// The exact value of integers, and the truncated value of others.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self.0.parse() {
+            Ok(i) => Some(i),
+            Err(_) => Some(self.as_f64())
+                .filter(|f| f.is_finite())
+                .map(|f| f as i64),
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Number<'a> {
+    fn from(s: &'a str) -> Self {
+        Number(Cow::Borrowed(s))
+    }
+}
+
+impl fmt::Display for Number<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A parsed value. Strings without escapes, keys and numbers borrow from the
+/// input instead of being copied.
+#[derive(Debug, PartialEq, Clone)]
+pub enum JsonValue<'a> {
+    Str(Cow<'a, str>),
     Null,
-    Num(f64),
+    Num(Number<'a>),
     Bool(bool),
-    Object(HashMap<String, JsonValue>),
-    Array(Vec<JsonValue>),
+    Object(Map<'a>),
+    Array(Vec<JsonValue<'a>>),
 }
 
-impl JsonValue {
-    pub fn map_value(&self, key: &str) -> Result<&JsonValue> {
+impl<'a> JsonValue<'a> {
+    pub fn map_value(&self, key: &str) -> Result<&JsonValue<'a>> {
         let map = match self {
             JsonValue::Object(x) => x,
             _ => return Err(eyre!("map_value with key '{key}' on non-object: {self:?}")),
@@ -37,38 +109,49 @@ impl JsonValue {
         }
     }
 
-    pub fn int_value(&self) -> Result<f64> {
+    pub fn int_value(&self) -> Result<i64> {
         match self {
-            JsonValue::Num(x) => Ok(*x as f64),
+            JsonValue::Num(x) => x.as_i64().ok_or_else(|| eyre!("int_value on {}", x)),
             _ => Err(eyre!("int_value on non-numeric")),
         }
     }
 
-    pub fn str_value(&self) -> Result<String> {
+    pub fn str_value(&self) -> Result<&str> {
         match self {
-            JsonValue::Str(x) => Ok(x.clone()),
+            JsonValue::Str(x) => Ok(x),
             _ => Err(eyre!("str_value on non-string")),
         }
     }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            JsonValue::Str(_) => "string",
+            JsonValue::Null => "null",
+            JsonValue::Num(_) => "number",
+            JsonValue::Bool(_) => "bool",
+            JsonValue::Object(_) => "object",
+            JsonValue::Array(_) => "array",
+        }
+    }
 }
 
-fn space<'a>(i: &'a str) -> IResult<&'a str, &'a str> {
+fn space(i: &str) -> IResult<&str, &str> {
     let chars = " \t\r\n";
     take_while(move |c| chars.contains(c))(i)
 }
 
-fn null<'a>(i: &'a str) -> IResult<&'a str, JsonValue> {
-    tag("null")(i).and_then(|(i, _o)| Ok((i, JsonValue::Null)))
+fn null(i: &str) -> IResult<&str, JsonValue<'_>> {
+    tag("null")(i).map(|(i, _o)| (i, JsonValue::Null))
 }
 
-fn bool<'a>(input: &'a str) -> IResult<&'a str, JsonValue> {
+fn bool(input: &str) -> IResult<&str, JsonValue<'_>> {
     let parse_true = value(JsonValue::Bool(true), tag("true"));
     let parse_false = value(JsonValue::Bool(false), tag("false"));
 
     alt((parse_true, parse_false))(input)
 }
 
-fn key_value<'a>(i: &'a str) -> IResult<&'a str, (String, JsonValue)> {
+fn key_value(i: &str) -> IResult<&str, (Cow<'_, str>, JsonValue<'_>)> {
     separated_pair(
         preceded(space, string),
         cut(preceded(space, char(':'))),
@@ -76,7 +159,7 @@ fn key_value<'a>(i: &'a str) -> IResult<&'a str, (String, JsonValue)> {
     )(i)
 }
 
-fn hash<'a>(i: &'a str) -> IResult<&'a str, HashMap<String, JsonValue>> {
+fn hash(i: &str) -> IResult<&str, Map<'_>> {
     preceded(
         char('{'),
         cut(terminated(
@@ -93,7 +176,7 @@ fn hash<'a>(i: &'a str) -> IResult<&'a str, HashMap<String, JsonValue>> {
 /// accumulating results in a `Vec`, until it encounters an error.
 /// If you want more control on the parser application, check out the `iterator`
 /// combinator (cf `examples/iterator.rs`)
-fn array<'a>(i: &'a str) -> IResult<&'a str, Vec<JsonValue>> {
+fn array(i: &str) -> IResult<&str, Vec<JsonValue<'_>>> {
     preceded(
         char('['),
         cut(terminated(
@@ -103,13 +186,13 @@ fn array<'a>(i: &'a str) -> IResult<&'a str, Vec<JsonValue>> {
     )(i)
 }
 
-fn json_value<'a>(i: &'a str) -> IResult<&'a str, JsonValue> {
+fn json_value(i: &str) -> IResult<&str, JsonValue<'_>> {
     preceded(
         space,
         alt((
             null,
             bool,
-            map(double, JsonValue::Num),
+            map(recognize(double), |n: &str| JsonValue::Num(n.into())),
             map(string, JsonValue::Str),
             map(hash, JsonValue::Object),
             map(array, JsonValue::Array),
@@ -117,18 +200,72 @@ fn json_value<'a>(i: &'a str) -> IResult<&'a str, JsonValue> {
     )(i)
 }
 
-pub fn root<'a>(i: &'a str) -> IResult<&'a str, JsonValue> {
+pub fn root(i: &str) -> IResult<&str, JsonValue<'_>> {
     preceded(space, map(hash, JsonValue::Object))(i)
 }
 
-fn string<'a>(i: &'a str) -> IResult<&'a str, String> {
+fn string(i: &str) -> IResult<&str, Cow<'_, str>> {
     preceded(char('\"'), cut(terminated(string_inner, char('\"'))))(i)
 }
 
-fn string_inner<'a>(i: &'a str) -> IResult<&'a str, String> {
+/// Reads the 4 hex digits of a `\u` escape.
+fn hex_escape<'a>(iterator: &mut str::Chars<'a>, i: &'a str) -> Result<u32, Err<Error<&'a str>>> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let digit = match iterator.next() {
+            Some(c) => c
+                .to_digit(16)
+                .ok_or_else(|| Err::Failure(Error::from_error_kind(i, ErrorKind::HexDigit)))?,
+            None => return Err(Err::Incomplete(Needed::new(1))),
+        };
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
+
+/// Decodes a `\u` escape, combining UTF-16 surrogate pairs. Unpaired
+/// surrogates, which are valid JSON but not valid Unicode, become U+FFFD.
+fn unicode_escape<'a>(
+    iterator: &mut str::Chars<'a>,
+    i: &'a str,
+) -> Result<char, Err<Error<&'a str>>> {
+    let code = hex_escape(iterator, i)?;
+    let code = match code {
+        0xD800..=0xDBFF => {
+            let mut lookahead = iterator.clone();
+            match (lookahead.next(), lookahead.next()) {
+                (Some('\\'), Some('u')) => match hex_escape(&mut lookahead, i)? {
+                    low @ 0xDC00..=0xDFFF => {
+                        *iterator = lookahead;
+                        0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00)
+                    }
+                    _ => code,
+                },
+                (None, _) | (Some('\\'), None) => return Err(Err::Incomplete(Needed::new(1))),
+                _ => code,
+            }
+        }
+        code => code,
+    };
+    Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+}
+
+fn string_inner(i: &str) -> IResult<&str, Cow<'_, str>> {
+    // Most strings have no escapes, and can be borrowed from the input.
+    match i.find(['"', '\\']) {
+        Some(end) if i.as_bytes()[end] == b'"' => Ok((&i[end..], Cow::Borrowed(&i[..end]))),
+        Some(end) => escaped_string(&i[..end], &i[end..]).map(|(i, s)| (i, Cow::Owned(s))),
+        None => Err(Err::Incomplete(Needed::new(1))),
+    }
+}
+
+/// Reads the rest of a string that has escapes in it, after the `start` that
+/// has none.
+fn escaped_string<'a>(start: &str, i: &'a str) -> IResult<&'a str, String> {
     // Although this could have been solved with parser combinators, it was
     // twice as fast with hand coding.
-    let mut buffer = String::with_capacity(256 as usize);
+    let mut buffer = String::with_capacity(start.len() + i.len().min(256));
+    buffer.push_str(start);
     let mut iterator = i.chars();
 
     loop {
@@ -142,33 +279,18 @@ fn string_inner<'a>(i: &'a str) -> IResult<&'a str, String> {
         } else if c == '\\' {
             let escaped_c = match iterator.next() {
                 Some(c) => c,
-                None => {
-                    return Err(Err::Failure(Error::from_error_kind(i, ErrorKind::Char)));
-                }
+                None => break,
             };
             buffer.push(match escaped_c {
+                '"' => '"',
+                '\\' => '\\',
+                '/' => '/',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
                 'n' => '\n',
+                'r' => '\r',
                 't' => '\t',
-                '\\' => '\\',
-                '"' => '"',
-                'u' => {
-                    let mut digits = String::new();
-                    for _ in 0..4 {
-                        digits.push(match iterator.next() {
-                            Some(c) => c,
-                            None => {
-                                return Err(Err::Failure(Error::from_error_kind(
-                                    i,
-                                    ErrorKind::Char,
-                                )));
-                            }
-                        })
-                    }
-                    let num = u32::from_str_radix(&digits, 16).expect("Couldn't parse str radix");
-                    let c = std::char::from_u32(num)
-                        .expect("Couldn't create char from parsed str radix");
-                    c
-                }
+                'u' => unicode_escape(&mut iterator, i)?,
                 _ => return Err(Err::Failure(Error::from_error_kind(i, ErrorKind::Char))),
             });
         } else {
@@ -176,7 +298,291 @@ fn string_inner<'a>(i: &'a str) -> IResult<&'a str, String> {
         }
     }
 
-    return Err(Err::Incomplete(Needed::new(1)));
+    Err(Err::Incomplete(Needed::new(1)))
+}
+
+/// What [`Scanner::scan`] found in the buffer.
+#[derive(Debug, PartialEq)]
+pub enum Scan {
+    /// The root object isn't finished yet.
+    Incomplete,
+    /// The root object ends at this byte offset.
+    Complete(usize),
+    /// The input doesn't start with an object, so it's plain text.
+    Text,
+    /// The input started like an object, but isn't valid JSON at this offset.
+    Invalid(usize),
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum State {
+    #[default]
+    Start,
+    Key,
+    KeyOrEnd,
+    Colon,
+    Value,
+    ValueOrEnd,
+    CommaOrEnd,
+    Str {
+        key: bool,
+    },
+    /// After a `\` in a string.
+    Escape {
+        key: bool,
+    },
+    /// In the hex digits of a `\u` escape, with `left` still to come.
+    Hex {
+        key: bool,
+        left: u8,
+    },
+    /// A number or literal starting at this offset.
+    Token(usize),
+}
+
+/// Finds where the root object of a buffer ends, and where its fields are,
+/// without building its value.
+///
+/// The scanner remembers how far it got, so when more input is appended to
+/// the buffer, scanning resumes there instead of starting over. Everything
+/// [`root`] would reject is found here, so the values can be parsed later,
+/// when they are needed.
+#[derive(Debug, Default)]
+pub struct Scanner {
+    pos: usize,
+    /// The open objects and arrays, as `{` and `[`.
+    stack: Vec<u8>,
+    state: State,
+    /// The key of the root object field being scanned, and where its value
+    /// starts.
+    key: Range<usize>,
+    value_start: usize,
+    fields: Vec<(Range<usize>, Range<usize>)>,
+    lines: usize,
+}
+
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.')
+}
+
+fn is_literal(token: &str) -> bool {
+    matches!(token, "true" | "false" | "null") || is_number(token)
+}
+
+/// Whether `token` is a number in JSON's grammar, which has no `+`, `inf` or
+/// `nan`, leading zeros, or dots without digits on both sides.
+fn is_number(token: &str) -> bool {
+    let digits = |s: &str| s.len() - s.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let rest = token.strip_prefix('-').unwrap_or(token);
+    let integer = digits(rest);
+    if integer == 0 || (integer > 1 && rest.starts_with('0')) {
+        return false;
+    }
+    let mut rest = &rest[integer..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        if digits(fraction) == 0 {
+            return false;
+        }
+        rest = &fraction[digits(fraction)..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        return digits(exponent) > 0 && digits(exponent) == exponent.len();
+    }
+    rest.is_empty()
+}
+
+impl Scanner {
+    /// Scans `buffer`, which must start with everything passed to the
+    /// previous calls.
+    pub fn scan(&mut self, buffer: &str) -> Scan {
+        let bytes = buffer.as_bytes();
+        while let Some(&b) = bytes.get(self.pos) {
+            let state = self.state;
+            let in_root = self.stack.len() == 1;
+            self.pos += 1;
+            // The byte after a token is scanned twice.
+            if b == b'\n' && !matches!(state, State::Token(_)) {
+                self.lines += 1;
+            }
+            let next = match (state, b) {
+                (State::Escape { key }, b'u') => State::Hex { key, left: 4 },
+                (State::Escape { key }, b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => {
+                    State::Str { key }
+                }
+                (State::Escape { .. }, _) => return Scan::Invalid(self.pos - 1),
+                (State::Hex { key, left }, b) if b.is_ascii_hexdigit() => match left {
+                    1 => State::Str { key },
+                    _ => State::Hex {
+                        key,
+                        left: left - 1,
+                    },
+                },
+                (State::Hex { .. }, _) => return Scan::Invalid(self.pos - 1),
+                (State::Str { key }, b'\\') => State::Escape { key },
+                (State::Str { key: true }, b'"') => {
+                    if in_root {
+                        self.key.end = self.pos - 1;
+                    }
+                    State::Colon
+                }
+                (State::Str { key: false }, b'"') => State::CommaOrEnd,
+                (State::Str { .. }, _) => state,
+                (State::Token(_), b) if is_token_byte(b) => state,
+                (State::Token(start), _) => {
+                    if !is_literal(&buffer[start..self.pos - 1]) {
+                        return Scan::Invalid(start);
+                    }
+                    // Scan the byte after the token again.
+                    self.pos -= 1;
+                    State::CommaOrEnd
+                }
+                // A blank line is text of its own, instead of waiting for
+                // what comes after it.
+                (State::Start, b'\n') => return Scan::Text,
+                (_, b' ' | b'\t' | b'\r' | b'\n') => state,
+                (State::Start, b'{') => {
+                    self.stack.push(b);
+                    State::KeyOrEnd
+                }
+                (State::Start, _) => return Scan::Text,
+                (State::Key | State::KeyOrEnd, b'"') => {
+                    if in_root {
+                        self.key.start = self.pos;
+                    }
+                    State::Str { key: true }
+                }
+                (State::Colon, b':') => State::Value,
+                (State::Value | State::ValueOrEnd, _) if in_root && is_value_start(b) => {
+                    self.value_start = self.pos - 1;
+                    self.start_value(b)
+                }
+                (State::Value | State::ValueOrEnd, _) if is_value_start(b) => self.start_value(b),
+                (State::CommaOrEnd, b',') => match self.stack.last() {
+                    Some(b'{') => State::Key,
+                    _ => State::Value,
+                },
+                (State::KeyOrEnd | State::CommaOrEnd, b'}')
+                | (State::ValueOrEnd | State::CommaOrEnd, b']') => {
+                    let open = if b == b'}' { b'{' } else { b'[' };
+                    if self.stack.pop() != Some(open) {
+                        return Scan::Invalid(self.pos - 1);
+                    }
+                    if self.stack.is_empty() {
+                        return Scan::Complete(self.pos);
+                    }
+                    State::CommaOrEnd
+                }
+                _ => return Scan::Invalid(self.pos - 1),
+            };
+            // A value of the root object has ended when the scanner gets back
+            // to waiting for its comma or `}`.
+            let waiting = matches!(next, State::CommaOrEnd) && self.stack.len() == 1;
+            if waiting && !(in_root && matches!(state, State::CommaOrEnd)) {
+                let value = self.value_start..self.pos;
+                self.fields.push((self.key.clone(), value));
+            }
+            self.state = next;
+        }
+        Scan::Incomplete
+    }
+
+    /// The number of line breaks scanned so far.
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    fn start_value(&mut self, b: u8) -> State {
+        match b {
+            b'"' => State::Str { key: false },
+            b'{' => {
+                self.stack.push(b);
+                State::KeyOrEnd
+            }
+            b'[' => {
+                self.stack.push(b);
+                State::ValueOrEnd
+            }
+            _ => State::Token(self.pos - 1),
+        }
+    }
+
+    /// The complete root object in `text`, which is the buffer up to where
+    /// [`Scan::Complete`] said it ends.
+    pub fn into_object(self, text: &str) -> RawObject {
+        self.into_object_in(&Arc::new(text.to_string()), 0..text.len())
+    }
+
+    /// Like [`Scanner::into_object`], but for an object at `range` of a
+    /// buffer that's shared with the other objects read from it, instead of
+    /// copied.
+    pub fn into_object_in(self, source: &Arc<String>, range: Range<usize>) -> RawObject {
+        RawObject {
+            source: source.clone(),
+            range,
+            fields: self.fields,
+        }
+    }
+}
+
+fn is_value_start(b: u8) -> bool {
+    matches!(b, b'"' | b'{' | b'[') || is_token_byte(b)
+}
+
+/// An object kept as text, with the positions of its fields, so that only the
+/// values that are looked up get parsed.
+pub struct RawObject {
+    /// The input the object was read from.
+    source: Arc<String>,
+    range: Range<usize>,
+    /// The key, without quotes, and the value of each field, from the start
+    /// of the object.
+    fields: Vec<(Range<usize>, Range<usize>)>,
+}
+
+impl fmt::Debug for RawObject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RawObject").field(&self.text()).finish()
+    }
+}
+
+impl RawObject {
+    /// The object as it was written.
+    pub fn text(&self) -> &str {
+        &self.source[self.range.clone()]
+    }
+
+    /// The value of a field. Like [`Map`], the last one wins when a key is
+    /// repeated.
+    pub fn get(&self, key: &str) -> Option<JsonValue<'_>> {
+        let text = self.text();
+        let (_, value) = self.fields.iter().rev().find(|(k, _)| {
+            let raw_key = &text[k.clone()];
+            match raw_key.contains('\\') {
+                false => raw_key == key,
+                true => string_inner(&text[k.start..]).is_ok_and(|(_, k)| k == key),
+            }
+        })?;
+        // Numbers need the byte after them to be known to have ended.
+        json_value(&text[value.start..])
+            .ok()
+            .map(|(_, value)| value)
+    }
+
+    /// The keys of the fields as written, so with any escapes left in.
+    pub fn raw_keys(&self) -> impl Iterator<Item = &str> {
+        self.fields
+            .iter()
+            .map(move |(key, _)| &self.text()[key.clone()])
+    }
+
+    /// All the fields.
+    pub fn map(&self) -> Map<'_> {
+        match root(self.text()) {
+            Ok((_, JsonValue::Object(map))) => map,
+            _ => Map::new(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -204,14 +610,14 @@ mod test {
     #[test]
     fn test_unicode_letter_string() {
         let parsed = string("\"\\u003d\"");
-        assert_eq!(parsed, Ok(("", "=".to_string())));
+        assert_eq!(parsed, Ok(("", "=".into())));
     }
 
     #[test]
     fn test_string_with_newline() {
         let input = "\"a\\nb\"";
         let parsed = string(input);
-        assert_eq!(parsed, Ok(("", "a\nb".to_string())));
+        assert_eq!(parsed, Ok(("", "a\nb".into())));
     }
 
     #[test]
@@ -219,7 +625,106 @@ mod test {
         let input = r#""This is a string with '\"' quotes.""#;
         let expected = r#"This is a string with '"' quotes."#.to_string();
         let parsed = string(input);
-        assert_eq!(parsed, Ok(("", expected)));
+        assert_eq!(parsed, Ok(("", expected.into())));
+    }
+
+    #[test]
+    fn borrows_unescaped_strings() {
+        assert!(matches!(
+            string("\"abc\","),
+            Ok((",", Cow::Borrowed("abc")))
+        ));
+        assert!(matches!(string("\"a\\tc\","), Ok((",", Cow::Owned(s))) if s == "a\tc"));
+        assert!(matches!(string("\"abc"), Err(Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn decodes_all_escapes() {
+        let parsed = string(r#""\/\b\f\r\t\u00e6\ud83d\ude00 \ud83d!""#);
+        assert_eq!(parsed, Ok(("", "/\u{8}\u{c}\r\tæ😀 \u{fffd}!".into())));
+        assert!(matches!(string(r#""\x""#), Err(Err::Failure(_))));
+        assert!(matches!(string(r#""\u12g4""#), Err(Err::Failure(_))));
+        assert!(matches!(string(r#""\u12"#), Err(Err::Incomplete(_))));
+        assert!(matches!(string(r#""\ud83d"#), Err(Err::Incomplete(_))));
+    }
+
+    #[test]
+    fn keeps_numbers_as_written() {
+        let number = |input| match json_value(input) {
+            Ok((",", JsonValue::Num(n))) => n,
+            other => panic!("Not a number: {:?}", other),
+        };
+        let big = number("1234567890123456789,");
+        assert_eq!("1234567890123456789", big.to_string());
+        assert_eq!(Some(1234567890123456789), big.as_i64());
+        assert_eq!(
+            "18446744073709551615",
+            number("18446744073709551615,").to_string()
+        );
+        assert_eq!("1.50", number("1.50,").to_string());
+        assert_eq!(1.5, number("1.50,").as_f64());
+        assert_eq!("-2e3", number("-2e3,").to_string());
+        assert_eq!(Some(-2000), number("-2e3,").as_i64());
+    }
+
+    #[test]
+    fn scans_across_pushes() {
+        let mut scanner = Scanner::default();
+        let mut buffer = String::from("  {\"a\": [1, -2.5e3, \"}\\\"\"], ");
+        assert_eq!(Scan::Incomplete, scanner.scan(&buffer));
+        buffer.push_str("\"b\": {\"c\": tr");
+        assert_eq!(Scan::Incomplete, scanner.scan(&buffer));
+        buffer.push_str("ue}}\n{}");
+        assert_eq!(Scan::Complete(buffer.len() - 3), scanner.scan(&buffer));
+
+        let scan = |input| Scanner::default().scan(input);
+        assert_eq!(Scan::Text, scan(" [1]"));
+        assert_eq!(Scan::Invalid(8), scan("{\"a\": 1 x}"));
+        assert_eq!(Scan::Invalid(6), scan("{\"a\": tru }"));
+        assert_eq!(Scan::Invalid(8), scan("{\"a\": [1}"));
+        assert_eq!(Scan::Invalid(8), scan("{\"a\": 1,}"));
+        assert_eq!(Scan::Text, scan("  \n{}"));
+    }
+
+    #[test]
+    fn scans_json_numbers_only() {
+        let scan = |number| Scanner::default().scan(&format!("{{\"a\": {number}}}"));
+        for number in ["0", "-0", "10", "1.5", "-2.5e3", "1E+5", "1e-05"] {
+            assert_eq!(Scan::Complete(7 + number.len()), scan(number), "{}", number);
+        }
+        for number in [
+            "inf", "NaN", "+5", ".5", "5.", "05", "1e", "1e+", "0x1", "1.5.2",
+        ] {
+            assert_eq!(Scan::Invalid(6), scan(number), "{}", number);
+        }
+    }
+
+    #[test]
+    fn looks_up_fields_lazily() {
+        let text =
+            r#" {"n": 1, "s": "a\"b", "o": {"x": [1, {}]}, "a": [], "k\u0065y": true, "n": 2.5}"#;
+        let mut scanner = Scanner::default();
+        assert_eq!(Scan::Complete(text.len()), scanner.scan(text));
+        let object = scanner.into_object(text);
+        let number = |value: &'static str| JsonValue::Num(value.into());
+        assert_eq!(Some(number("2.5")), object.get("n"));
+        assert_eq!(Some(JsonValue::Str("a\"b".into())), object.get("s"));
+        assert_eq!(Some(JsonValue::Array(vec![])), object.get("a"));
+        assert_eq!(Some(JsonValue::Bool(true)), object.get("key"));
+        assert_eq!(None, object.get("x"));
+        let inner = object.get("o").unwrap();
+        assert_eq!(
+            &JsonValue::Array(vec![number("1"), JsonValue::Object(Map::new())]),
+            inner.map_value("x").unwrap()
+        );
+        assert_eq!(
+            vec!["n", "s", "o", "a", "key"],
+            object.map().keys().collect::<Vec<_>>()
+        );
+
+        let scan = |input| Scanner::default().scan(input);
+        assert_eq!(Scan::Invalid(8), scan(r#"{"a": "\x"}"#));
+        assert_eq!(Scan::Invalid(11), scan(r#"{"a": "\u12g4"}"#));
     }
 
     // Property based tests
@@ -254,6 +759,7 @@ mod test {
         fn can_parse(input in arb_json_str(&mut Default::default())){
             let (remainder, _obj) = root(&input)?;
             prop_assert_eq!("", remainder);
+            prop_assert_eq!(Scan::Complete(input.len()), Scanner::default().scan(&input));
         }
     }
 }
@@ -0,0 +1,120 @@
+use chrono::{DateTime, Utc};
+
+/// Text in front of the JSON of a line, as written by container runtimes and
+/// by tools that merge the logs of several sources.
+#[derive(Debug, PartialEq)]
+pub struct Prefix {
+    /// Where the JSON starts.
+    pub len: usize,
+    /// When the line was written, for entries without a timestamp of their
+    /// own.
+    pub time: Option<DateTime<Utc>>,
+    /// Fields for the entry, like the source the line came from.
+    pub labels: Vec<(String, String)>,
+    /// Whether the rest of the line is continued on the next line of the
+    /// same stream, as CRI does with long lines.
+    pub partial: bool,
+}
+
+impl Prefix {
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label, _)| *label == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Finds the prefix of a line whose JSON doesn't start at the beginning. Knows
+/// the containerd CRI format, `2023-10-01T12:00:00Z stdout F {...}`, docker
+/// compose, `web-1  | {...}`, and `kubectl logs --prefix`,
+/// `[pod/api-7f9/app] {...}`. CRI lines are recognized whatever follows the
+/// prefix, since a line can be the continuation of a partial one.
+pub fn find_prefix(line: &str) -> Option<Prefix> {
+    cri(line).or_else(|| {
+        let prefix = kubectl(line).or_else(|| compose(line))?;
+        line[prefix.len..].starts_with('{').then_some(prefix)
+    })
+}
+
+fn cri(line: &str) -> Option<Prefix> {
+    let mut parts = line.splitn(4, ' ');
+    let time = DateTime::parse_from_rfc3339(parts.next()?).ok()?;
+    let stream = parts.next().filter(|s| matches!(*s, "stdout" | "stderr"))?;
+    let tag = parts.next().filter(|tag| matches!(*tag, "F" | "P"))?;
+    Some(Prefix {
+        len: line.len() - parts.next()?.len(),
+        time: Some(time.with_timezone(&Utc)),
+        labels: vec![("stream".to_string(), stream.to_string())],
+        partial: tag == "P",
+    })
+}
+
+fn kubectl(line: &str) -> Option<Prefix> {
+    let (source, _) = line.strip_prefix('[')?.split_once("] ")?;
+    if source.contains(char::is_whitespace) || !source.contains('/') {
+        return None;
+    }
+    Some(Prefix {
+        len: source.len() + 3,
+        time: None,
+        labels: vec![("source".to_string(), source.to_string())],
+        partial: false,
+    })
+}
+
+fn compose(line: &str) -> Option<Prefix> {
+    let (name, _) = line.split_once(' ')?;
+    let rest = line[name.len()..].trim_start_matches(' ');
+    let payload = rest.strip_prefix("| ")?;
+    if name.is_empty() {
+        return None;
+    }
+    Some(Prefix {
+        len: line.len() - payload.len(),
+        time: None,
+        labels: vec![("source".to_string(), name.to_string())],
+        partial: false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn finds_prefixes() {
+        let labels = |line| find_prefix(line).map(|prefix| (prefix.len, prefix.labels));
+        let line = "2023-10-01T12:00:00.5+02:00 stderr F {\"a\": 1}";
+        let prefix = find_prefix(line).unwrap();
+        assert_eq!(37, prefix.len);
+        assert_eq!(
+            "2023-10-01T10:00:00.500",
+            prefix.time.unwrap().format("%FT%T%.3f").to_string()
+        );
+        assert_eq!(
+            vec![("stream".to_string(), "stderr".to_string())],
+            prefix.labels
+        );
+        let continued = find_prefix("2023-10-01T12:00:00Z stdout P ue}").unwrap();
+        assert_eq!((30, true), (continued.len, continued.partial));
+        assert_eq!(None, labels("2023-10-01T12:00:00Z stdout FP {}"));
+
+        assert_eq!(
+            Some((9, vec![("source".to_string(), "web-1".to_string())])),
+            labels("web-1  | {}")
+        );
+        assert_eq!(
+            Some((
+                18,
+                vec![("source".to_string(), "pod/api-7f9/app".to_string())]
+            )),
+            labels("[pod/api-7f9/app] {}")
+        );
+        assert_eq!(None, labels("web-1  | plain text"));
+        assert_eq!(None, labels("[INFO] {}"));
+        assert_eq!(None, labels(" | {}"));
+        assert_eq!(None, labels("2023-10-01 stdout F {}"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::io::Write;
+
+use clap::ValueEnum as ClapValueEnum;
+
+use crate::color::Style;
+use crate::parser::{JsonValue, Map};
+use crate::theme::{theme, Theme};
+use crate::PrintConfig;
+
+#[derive(ClapValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum FieldOrder {
+    /// Sorted by key.
+    Sorted,
+    /// The order the logger wrote the fields in.
+    Original,
+}
+
+/// Keys that are already shown in the first line of an entry.
+const SHOWN_KEYS: [&str; 4] = ["timestamp", "@timestamp", "severity", "level"];
+
+/// Writes `value` as single-line JSON without colors, eg. for `--extra` values
+/// and filters, with the keys of objects sorted if `sort`.
+pub fn to_json(value: &JsonValue, sort: bool) -> String {
+    json(value, None, sort)
+}
+
+fn keys<'a>(map: &'a Map, sort: bool) -> Vec<&'a str> {
+    let mut keys: Vec<_> = map.keys().map(|key| key.as_ref()).collect();
+    if sort {
+        keys.sort();
+    }
+    keys
+}
+
+fn json(value: &JsonValue, theme: Option<&Theme>, sort: bool) -> String {
+    let paint = |style: fn(&Theme) -> Style, text: String| match theme {
+        Some(theme) => style(theme).paint(text).to_string(),
+        None => text,
+    };
+    match value {
+        JsonValue::Null => paint(|t| t.literal, "null".to_string()),
+        JsonValue::Bool(b) => paint(|t| t.literal, b.to_string()),
+        JsonValue::Num(n) => paint(|t| t.number, n.to_string()),
+        JsonValue::Str(s) => paint(|t| t.string, quote(s)),
+        JsonValue::Array(items) => {
+            let items: Vec<_> = items.iter().map(|item| json(item, theme, sort)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        JsonValue::Object(map) => {
+            let entries: Vec<_> = keys(map, sort)
+                .into_iter()
+                .map(|key| {
+                    format!(
+                        "{}: {}",
+                        paint(|t| t.key, quote(key)),
+                        json(&map[key], theme, sort)
+                    )
+                })
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+    }
+}
+
+/// Quotes and escapes `s` as a JSON string.
+fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn is_scalar(value: &JsonValue) -> bool {
+    !matches!(value, JsonValue::Array(_) | JsonValue::Object(_))
+}
+
+/// A value in the tree form: strings unquoted, and arrays of scalars inline.
+/// None for values that are written on their own lines.
+fn tree_value(value: &JsonValue, indent: &str, config: &PrintConfig) -> Option<String> {
+    let theme = theme();
+    match value {
+        JsonValue::Str(s) if s.contains('\n') => {
+            let line_prefix = format!("\n{indent}  ");
+            Some(format!(
+                "{line_prefix}{}",
+                theme.string.paint(s.replace('\n', &line_prefix))
+            ))
+        }
+        JsonValue::Str(s) => Some(theme.string.paint(s).to_string()),
+        JsonValue::Array(items) if !config.compact && !items.iter().all(is_scalar) => None,
+        JsonValue::Object(map) if !config.compact && !map.is_empty() => None,
+        value => Some(json(value, Some(theme), config.sort_fields)),
+    }
+}
+
+/// Writes the fields of an entry, one per line, with nested objects as
+/// indented trees and arrays of objects as list items. With `compact`, nested
+/// values are written as inline JSON instead.
+pub fn write_map<W>(
+    f: &mut W,
+    map: &Map,
+    indent: &str,
+    message_path: Option<&str>,
+    config: &PrintConfig,
+) -> std::io::Result<()>
+where
+    W: Write,
+{
+    let key_style = theme().key;
+    for key in keys(map, config.sort_fields) {
+        if SHOWN_KEYS.contains(&key) || Some(key) == message_path {
+            continue;
+        }
+        let value = &map[key];
+        match (value, tree_value(value, indent, config)) {
+            (JsonValue::Null, _) => {}
+            (JsonValue::Object(map), _) if map.is_empty() && !config.compact => {}
+            (JsonValue::Object(map), None) => {
+                writeln!(f, "{}{}:", indent, key_style.paint(key))?;
+                write_map(f, map, &format!("  {}", indent), message_path, config)?;
+            }
+            (JsonValue::Array(items), None) => {
+                writeln!(f, "{}{}:", indent, key_style.paint(key))?;
+                write_list(f, items, &format!("  {}", indent), message_path, config)?;
+            }
+            (_, Some(value)) => writeln!(f, "{}{} = {}", indent, key_style.paint(key), value)?,
+            (_, None) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Writes the items of an array as `- ` list items.
+fn write_list<W>(
+    f: &mut W,
+    items: &[JsonValue],
+    indent: &str,
+    message_path: Option<&str>,
+    config: &PrintConfig,
+) -> std::io::Result<()>
+where
+    W: Write,
+{
+    let item_indent = format!("{indent}  ");
+    for item in items {
+        match (item, tree_value(item, &item_indent, config)) {
+            (JsonValue::Object(map), None) => {
+                // Write the fields under the item, then put the dash in front
+                // of the first one.
+                let mut buffer = Vec::new();
+                write_map(&mut buffer, map, &item_indent, message_path, config)?;
+                let fields = String::from_utf8_lossy(&buffer);
+                match fields.strip_prefix(item_indent.as_str()) {
+                    Some(fields) => write!(f, "{indent}- {fields}")?,
+                    None => writeln!(f, "{indent}- {{}}")?,
+                }
+            }
+            (JsonValue::Array(items), None) => {
+                writeln!(f, "{indent}-")?;
+                write_list(f, items, &item_indent, message_path, config)?;
+            }
+            (_, Some(value)) => writeln!(f, "{indent}- {value}")?,
+            (_, None) => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Parser, ParserOutput};
+    use pretty_assertions::assert_eq;
+
+    fn fields(input: &str, compact: bool, sort_fields: bool) -> String {
+        colored::control::set_override(false);
+        let config = PrintConfig {
+            verbose: true,
+            compact,
+            sort_fields,
+            ..Default::default()
+        };
+        let line = match Parser::new().push(input).remove(0) {
+            ParserOutput::Log(line) => line,
+            output => panic!("Not a log line: {:?}", output),
+        };
+        let mut buffer = Vec::new();
+        write_map(&mut buffer, &line.fields(), "  ", Some("message"), &config).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    const INPUT: &str = r#"{"message": "a", "timestamp": "2022-04-01T18:49:52Z",
+        "ids": [1, 2.5, "x\"y"],
+        "hosts": [{"name": "a", "tags": ["web"], "port": 80}, {"name": "b"}, "other", [1, {"deep": true}]],
+        "http": {"status": 200, "ok": true, "empty": {}}}"#;
+
+    #[test]
+    fn writes_tree() {
+        let expected = r#"  hosts:
+    - name = a
+      port = 80
+      tags = ["web"]
+    - name = b
+    - other
+    -
+      - 1
+      - deep = true
+  http:
+    ok = true
+    status = 200
+  ids = [1, 2.5, "x\"y"]
+"#;
+        assert_eq!(expected, fields(INPUT, false, true));
+
+        let expected = r#"  ids = [1, 2.5, "x\"y"]
+  hosts:
+    - name = a
+      tags = ["web"]
+      port = 80
+    - name = b
+    - other
+    -
+      - 1
+      - deep = true
+  http:
+    status = 200
+    ok = true
+"#;
+        assert_eq!(expected, fields(INPUT, false, false));
+    }
+
+    #[test]
+    fn writes_compact() {
+        let expected = r#"  hosts = [{"name": "a", "port": 80, "tags": ["web"]}, {"name": "b"}, "other", [1, {"deep": true}]]
+  http = {"empty": {}, "ok": true, "status": 200}
+  ids = [1, 2.5, "x\"y"]
+"#;
+        assert_eq!(expected, fields(INPUT, true, true));
+        assert_eq!(
+            r#"{"a\nb": null}"#,
+            to_json(
+                &JsonValue::Object(Map::from([("a\nb".into(), JsonValue::Null)])),
+                true
+            )
+        );
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+
+use crate::parser::{JsonValue, Map};
+use crate::theme::theme;
+use crate::{format_value, LogLine, ParserOutput, PrintConfig};
+
+/// Keys describing which span an entry belongs to. In tree mode these are
+/// shown in the span headers instead of in every entry.
+pub const SPAN_KEYS: [&str; 8] = [
+    "span",
+    "spans",
+    "span_id",
+    "spanId",
+    "parent_span_id",
+    "parentSpanId",
+    "span_name",
+    "spanName",
+];
+
+#[derive(Debug, PartialEq, Clone)]
+struct Span {
+    id: String,
+    name: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Span {
+    /// A span as written by `tracing-subscriber`'s JSON formatter, eg.
+    /// `{"name": "request", "method": "GET"}`.
+    fn from_tracing(value: &JsonValue) -> Option<Span> {
+        let map = match value {
+            JsonValue::Object(map) => map,
+            _ => return None,
+        };
+        let name = map.get("name")?.str_value().ok()?.to_string();
+        let mut fields: Vec<_> = map
+            .iter()
+            .filter(|(key, _)| *key != "name" && *key != "id")
+            .filter_map(|(key, value)| Some((key.to_string(), format_value(value, true)?)))
+            .collect();
+        fields.sort();
+
+        // Spans without an explicit id are identified by their name and fields.
+        let id = match map.get("id").and_then(|id| format_value(id, true)) {
+            Some(id) => id,
+            None => format!("{name}{fields:?}"),
+        };
+        Some(Span { id, name, fields })
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let theme = theme();
+        write!(f, "{}", theme.span.paint(&self.name))?;
+        if !self.fields.is_empty() {
+            let fields: Vec<_> = self
+                .fields
+                .iter()
+                .map(|(key, value)| format!("{}={}", theme.key.paint(key), value))
+                .collect();
+            write!(f, "{{{}}}", fields.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints log entries indented under the span they were emitted in. A span
+/// header is written whenever an entry enters a span that differs from the
+/// previous entry's span path. Text lines stay under the span of the entry
+/// before them.
+#[derive(Default)]
+pub struct SpanTree {
+    current_path: Vec<Span>,
+    /// OpenTelemetry span id to parent span id, learned from earlier entries.
+    parents: HashMap<String, String>,
+    /// OpenTelemetry span id to span name, when the entries carry one.
+    names: HashMap<String, String>,
+}
+
+impl SpanTree {
+    pub fn print<W>(
+        &mut self,
+        f: &mut W,
+        output: &ParserOutput,
+        config: &PrintConfig,
+    ) -> std::io::Result<()>
+    where
+        W: Write,
+    {
+        let path = match output {
+            ParserOutput::Log(line) => self.span_path(line),
+            _ => self.current_path.clone(),
+        };
+        let common = self
+            .current_path
+            .iter()
+            .zip(&path)
+            .take_while(|(a, b)| a.id == b.id)
+            .count();
+        for (depth, span) in path.iter().enumerate().skip(common) {
+            writeln!(f, "{}{}", "  ".repeat(depth), span)?;
+        }
+
+        let indent = "  ".repeat(path.len());
+        let config = PrintConfig {
+            max_width: config.max_width.map(|w| w.saturating_sub(indent.len())),
+            ..config.clone()
+        };
+        let mut buffer = Vec::new();
+        output.print(&mut buffer, &config)?;
+        for l in String::from_utf8_lossy(&buffer).lines() {
+            writeln!(f, "{indent}{l}")?;
+        }
+
+        self.current_path = path;
+        Ok(())
+    }
+
+    fn span_path(&mut self, line: &LogLine) -> Vec<Span> {
+        let map = line.fields();
+        if let Some(JsonValue::Array(spans)) = map.get("spans") {
+            return spans.iter().filter_map(Span::from_tracing).collect();
+        }
+        if let Some(span) = map.get("span").and_then(Span::from_tracing) {
+            return vec![span];
+        }
+        self.otel_span_path(&map)
+    }
+
+    fn otel_span_path(&mut self, map: &Map) -> Vec<Span> {
+        let get = |keys: [&str; 2]| {
+            keys.iter()
+                .find_map(|key| map.get(*key).and_then(|v| v.str_value().ok()))
+                .map(str::to_string)
+                .filter(|v| !v.is_empty())
+        };
+        let span_id = match get(["span_id", "spanId"]) {
+            Some(span_id) => span_id,
+            None => return vec![],
+        };
+        if let Some(parent_id) = get(["parent_span_id", "parentSpanId"]) {
+            self.parents.insert(span_id.clone(), parent_id);
+        }
+        if let Some(name) = get(["span_name", "spanName"]) {
+            self.names.insert(span_id.clone(), name);
+        }
+
+        let mut path = vec![];
+        let mut visited = HashSet::new();
+        let mut id = Some(span_id);
+        while let Some(current) = id {
+            if !visited.insert(current.clone()) {
+                break;
+            }
+            let name = self.names.get(&current).unwrap_or(&current).clone();
+            id = self.parents.get(&current).cloned();
+            path.push(Span {
+                id: current,
+                name,
+                fields: vec![],
+            });
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Parser;
+    use pretty_assertions::assert_eq;
+
+    fn render(input: &str, verbose: bool) -> String {
+        colored::control::set_override(false);
+        let config = PrintConfig {
+            verbose,
+            span_tree: true,
+            ..Default::default()
+        };
+        let mut tree = SpanTree::default();
+        let mut buffer = Vec::new();
+        for output in Parser::new().push_lines(input) {
+            tree.print(&mut buffer, &output, &config).unwrap();
+        }
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn tracing_spans_are_printed_once() {
+        let input = r#"{"timestamp":"2024-01-01T00:00:00Z","level":"INFO","message":"started"}
+{"timestamp":"2024-01-01T00:00:01Z","level":"INFO","message":"a","span":{"name":"req","id":"1"},"spans":[{"name":"conn","peer":"10.0.0.1"},{"name":"req","path":"/"}]}
+{"timestamp":"2024-01-01T00:00:02Z","level":"DEBUG","message":"b","span":{"name":"req"},"spans":[{"name":"conn","peer":"10.0.0.1"},{"name":"req","path":"/"}]}
+thread panicked at src/main.rs:10:5
+{"timestamp":"2024-01-01T00:00:03Z","level":"INFO","message":"c","spans":[{"name":"conn","peer":"10.0.0.1"}]}
+{"timestamp":"2024-01-01T00:00:04Z","level":"INFO","message":"d","spans":[{"name":"conn","peer":"10.0.0.1"},{"name":"req","path":"/health"}]}"#;
+        let expected = r#"2024-01-01 00:00:00.000Z INFO    started
+conn{peer=10.0.0.1}
+  req{path=/}
+    2024-01-01 00:00:01.000Z INFO    a
+    2024-01-01 00:00:02.000Z DEBUG   b
+    thread panicked at src/main.rs:10:5
+  2024-01-01 00:00:03.000Z INFO    c
+  req{path=/health}
+    2024-01-01 00:00:04.000Z INFO    d
+"#;
+        assert_eq!(expected, render(input, false));
+    }
+
+    #[test]
+    fn otel_parent_span_ids() {
+        let input = r#"{"timestamp":"2024-01-01T00:00:00Z","level":"INFO","message":"a","span_id":"aa","span_name":"handler"}
+{"timestamp":"2024-01-01T00:00:01Z","level":"INFO","message":"b","span_id":"bb","parent_span_id":"aa","trace_id":"t1"}
+{"timestamp":"2024-01-01T00:00:02Z","level":"INFO","message":"c","span_id":"aa"}"#;
+        let expected = r#"handler
+  2024-01-01 00:00:00.000Z INFO    a
+  bb
+    2024-01-01 00:00:01.000Z INFO    b
+      trace_id = t1
+  2024-01-01 00:00:02.000Z INFO    c
+"#;
+        assert_eq!(expected, render(input, true));
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use chrono::{DateTime, Duration, Utc};
+use colored::Colorize;
+
+use crate::table::console_width;
+use crate::{Parser, ParserOutput, PrintConfig, Report, Severity};
+
+/// Keys commonly used for the name of the logger that produced an entry.
+const LOGGER_KEYS: [&str; 5] = [
+    "logger",
+    "logger_name",
+    "loggerName",
+    "target",
+    "sourceLocation.loggerName",
+];
+
+/// Maximum number of distinct values listed per field.
+const MAX_ROWS: usize = 10;
+
+/// Summary statistics over all entries in a stream, printed at the end of the
+/// run with `--stats` or `clog stats`.
+pub struct Stats {
+    entries: usize,
+    text_lines: usize,
+    severities: HashMap<Severity, usize>,
+    /// Value counts for each `--extra` field and the logger name.
+    fields: Vec<(String, Vec<String>, HashMap<String, usize>)>,
+    first: Option<DateTime<Utc>>,
+    last: Option<DateTime<Utc>>,
+}
+
+impl Stats {
+    pub fn new(extra: &[String]) -> Self {
+        let mut fields: Vec<_> = extra
+            .iter()
+            .map(|e| (e.clone(), vec![e.clone()], HashMap::new()))
+            .collect();
+        let logger_keys = LOGGER_KEYS.iter().map(|k| k.to_string()).collect();
+        fields.push(("logger".to_string(), logger_keys, HashMap::new()));
+        Stats {
+            entries: 0,
+            text_lines: 0,
+            severities: HashMap::new(),
+            fields,
+            first: None,
+            last: None,
+        }
+    }
+
+    fn with_percentage(&self, count: usize) -> String {
+        let percentage = 100.0 * count as f64 / self.entries.max(1) as f64;
+        format!("{count:>8} {percentage:>6.1}%")
+    }
+}
+
+impl Report for Stats {
+    fn add(&mut self, output: &ParserOutput) {
+        let line = match output {
+            ParserOutput::Log(line) => line,
+            ParserOutput::Text(text) => {
+                self.text_lines += text.lines().count();
+                return;
+            }
+            ParserOutput::None => return,
+        };
+
+        self.entries += 1;
+        *self.severities.entry(line.severity()).or_default() += 1;
+        self.first = Some(self.first.map_or(line.time, |t| t.min(line.time)));
+        self.last = Some(self.last.map_or(line.time, |t| t.max(line.time)));
+
+        for (_, keys, counts) in self.fields.iter_mut() {
+            let value = keys.iter().find_map(|key| line.value(key));
+            if let Some(value) = value {
+                *counts.entry(value).or_default() += 1;
+            }
+        }
+    }
+
+    fn print(
+        &self,
+        f: &mut dyn Write,
+        config: &PrintConfig,
+        parser: &Parser,
+    ) -> std::io::Result<()> {
+        let mut summary = vec![
+            ("Entries".to_string(), self.entries.to_string()),
+            ("Text lines".to_string(), self.text_lines.to_string()),
+            ("Parse failures".to_string(), parser.failures.to_string()),
+        ];
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            let span = last - first;
+            summary.push(("First entry".to_string(), config.format_time(&first)));
+            summary.push(("Last entry".to_string(), config.format_time(&last)));
+            summary.push(("Time span".to_string(), format_duration(span)));
+            if let Some(micros) = span.num_microseconds().filter(|m| *m > 0) {
+                let rate = self.entries as f64 / (micros as f64 / 1e6);
+                summary.push(("Entries/sec".to_string(), format!("{rate:.2}")));
+            }
+        }
+        write_table(f, "Summary", &summary)?;
+
+        let severities: Vec<_> = Severity::ALL
+            .iter()
+            .filter_map(|s| {
+                let count = *self.severities.get(s)?;
+                let name = format!("{s:?}").to_uppercase();
+                let name = s.colors().0.paint(name).to_string();
+                Some((name, self.with_percentage(count)))
+            })
+            .collect();
+        if !severities.is_empty() {
+            writeln!(f)?;
+            write_table(f, "Severity", &severities)?;
+        }
+
+        for (name, _, counts) in &self.fields {
+            if counts.is_empty() {
+                continue;
+            }
+            let mut sorted: Vec<_> = counts.iter().collect();
+            sorted.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+            let mut rows: Vec<_> = sorted
+                .iter()
+                .take(MAX_ROWS)
+                .map(|(value, count)| (value.to_string(), self.with_percentage(**count)))
+                .collect();
+            if sorted.len() > MAX_ROWS {
+                let others: usize = sorted[MAX_ROWS..].iter().map(|(_, c)| **c).sum();
+                let label = format!("({} others)", sorted.len() - MAX_ROWS);
+                rows.push((label, self.with_percentage(others)));
+            }
+            writeln!(f)?;
+            write_table(f, name, &rows)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_table(f: &mut dyn Write, title: &str, rows: &[(String, String)]) -> std::io::Result<()> {
+    writeln!(f, "{}", title.bold())?;
+    let width = rows
+        .iter()
+        .map(|(key, _)| console_width(key))
+        .max()
+        .unwrap_or(0);
+    for (key, value) in rows {
+        let padding = " ".repeat(width - console_width(key));
+        writeln!(f, "  {key}{padding}  {value}")?;
+    }
+    Ok(())
+}
+
+/// Formats a duration for humans, eg. `3.2s`, `4m 05s` or `2h 03m 00s`.
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.num_milliseconds();
+    let seconds = millis / 1000;
+    if seconds < 60 {
+        format!("{:.1}s", millis as f64 / 1000.0)
+    } else if seconds < 3600 {
+        format!("{}m {:02}s", seconds / 60, seconds % 60)
+    } else {
+        let hours = seconds / 3600;
+        format!(
+            "{}h {:02}m {:02}s",
+            hours,
+            (seconds % 3600) / 60,
+            seconds % 60
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::print_report;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn stats_for_example() {
+        colored::control::set_override(false);
+        let input = r#"{"message": "a", "timestamp": "2022-04-01T18:49:52.000Z", "severity": "ERROR", "sourceLocation": {"loggerName": "ingestion"}}
+Some plain text
+{"message": nope}
+{"message": "b", "timestamp": "2022-04-01T18:49:53.000Z", "severity": "INFO", "app": "x", "sourceLocation": {"loggerName": "ingestion"}}
+{"message": "c", "timestamp": "2022-04-01T18:49:56.000Z", "severity": "INFO", "app": "y"}
+{"message": "broken", "timestamp": "
+"#;
+        let config = PrintConfig {
+            extra: vec!["app".to_string()],
+            ..Default::default()
+        };
+        let mut stats = Stats::new(&config.extra);
+        let printed = print_report(&mut stats, input, &config);
+        let expected = r#"Summary
+  Entries         3
+  Text lines      3
+  Parse failures  1
+  First entry     2022-04-01 18:49:52.000Z
+  Last entry      2022-04-01 18:49:56.000Z
+  Time span       4.0s
+  Entries/sec     0.75
+
+Severity
+  INFO          2   66.7%
+  ERROR         1   33.3%
+
+app
+  x         1   33.3%
+  y         1   33.3%
+
+logger
+  ingestion         2   66.7%
+"#;
+        assert_eq!(expected, printed);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!("3.2s", format_duration(Duration::milliseconds(3200)));
+        assert_eq!("4m 05s", format_duration(Duration::seconds(245)));
+        assert_eq!("2h 03m 00s", format_duration(Duration::seconds(7380)));
+    }
+}
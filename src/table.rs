@@ -0,0 +1,64 @@
+use std::io::Write;
+
+use colored::Colorize;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// Writes `rows` as columns under a bold `header`. Cells may contain color
+/// escapes, which don't count towards the column widths.
+pub fn write_columns(
+    f: &mut dyn Write,
+    header: &[&str],
+    align: &[Align],
+    rows: &[Vec<String>],
+) -> std::io::Result<()> {
+    let mut widths: Vec<_> = header.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(console_width(cell));
+        }
+    }
+
+    let header: Vec<_> = header.iter().map(|h| h.to_string()).collect();
+    let line = format_row(&header, &widths, align);
+    writeln!(f, "{}", line.bold())?;
+    for row in rows {
+        writeln!(f, "{}", format_row(row, &widths, align))?;
+    }
+    Ok(())
+}
+
+fn format_row(row: &[String], widths: &[usize], align: &[Align]) -> String {
+    let cells: Vec<_> = row
+        .iter()
+        .zip(widths)
+        .zip(align)
+        .map(|((cell, width), align)| {
+            let padding = " ".repeat(width - console_width(cell));
+            match align {
+                Align::Left => format!("{cell}{padding}"),
+                Align::Right => format!("{padding}{cell}"),
+            }
+        })
+        .collect();
+    cells.join("  ").trim_end().to_string()
+}
+
+/// The number of characters in `s`, not counting ANSI escape sequences.
+pub fn console_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in s.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
@@ -0,0 +1,176 @@
+//! Normalizes log messages into templates by masking the parts that vary
+//! between otherwise identical messages, such as numbers and ids.
+
+const DURATION_UNITS: [&str; 9] = ["ns", "us", "µs", "ms", "s", "sec", "m", "min", "h"];
+
+/// Returns `message` with quoted strings, UUIDs, hex values, durations and
+/// numbers replaced by placeholders. Whitespace runs are collapsed, so that
+/// padded counters like `[ 389/9733]` and `[1167/9733]` share a template.
+pub fn template(message: &str) -> String {
+    let chars: Vec<char> = message.chars().collect();
+    let mut output = String::with_capacity(message.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() {
+                i += 1;
+            }
+            if !output.is_empty() && !output.ends_with(['[', '(', '{']) {
+                output.push(' ');
+            }
+            continue;
+        }
+
+        let at_word_start = i == 0 || !is_word(chars[i - 1]);
+        if at_word_start {
+            if let Some((length, placeholder)) = masked(&chars[i..]) {
+                output.push_str(placeholder);
+                i += length;
+                continue;
+            }
+        }
+        output.push(c);
+        i += 1;
+    }
+    output.trim_end().to_string()
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The length and placeholder of a maskable token at the start of `s`.
+fn masked(s: &[char]) -> Option<(usize, &'static str)> {
+    let ends_word = |n: usize| n == s.len() || !is_word(s[n]);
+
+    if let Some(n) = quoted(s) {
+        return Some((n, "<str>"));
+    }
+    if let Some(n) = uuid(s).filter(|n| ends_word(*n)) {
+        return Some((n, "<uuid>"));
+    }
+    if s.len() > 2 && s[0] == '0' && (s[1] == 'x' || s[1] == 'X') {
+        let n = 2 + hex_digits(&s[2..]);
+        if n > 2 && ends_word(n) {
+            return Some((n, "<hex>"));
+        }
+    }
+    if let Some(n) = duration(s).filter(|n| ends_word(*n)) {
+        return Some((n, "<duration>"));
+    }
+    if let Some(n) = number(s).filter(|n| ends_word(*n)) {
+        return Some((n, "<num>"));
+    }
+    let n = hex_digits(s);
+    if n >= 8 && ends_word(n) && s[..n].iter().any(|c| c.is_ascii_digit()) {
+        return Some((n, "<hex>"));
+    }
+    None
+}
+
+fn quoted(s: &[char]) -> Option<usize> {
+    let quote = *s.first()?;
+    if quote != '"' && quote != '\'' {
+        return None;
+    }
+    let end = s[1..].iter().position(|c| *c == quote)?;
+    Some(end + 2)
+}
+
+fn hex_digits(s: &[char]) -> usize {
+    s.iter().take_while(|c| c.is_ascii_hexdigit()).count()
+}
+
+fn uuid(s: &[char]) -> Option<usize> {
+    let mut n = 0;
+    for (i, group) in [8, 4, 4, 4, 12].iter().enumerate() {
+        if i > 0 {
+            if s.get(n) != Some(&'-') {
+                return None;
+            }
+            n += 1;
+        }
+        if s.len() < n + group || hex_digits(&s[n..n + group]) != *group {
+            return None;
+        }
+        n += group;
+    }
+    Some(n)
+}
+
+fn number(s: &[char]) -> Option<usize> {
+    let digits = |s: &[char]| s.iter().take_while(|c| c.is_ascii_digit()).count();
+    let mut n = digits(s);
+    if n == 0 {
+        return None;
+    }
+    if s.get(n) == Some(&'.') {
+        let decimals = digits(&s[n + 1..]);
+        if decimals > 0 {
+            n += 1 + decimals;
+        }
+    }
+    Some(n)
+}
+
+/// Durations like `150ms`, `3.2s` or `1h30m`.
+fn duration(s: &[char]) -> Option<usize> {
+    let mut n = 0;
+    while let Some(number_length) = number(&s[n..]) {
+        let rest = &s[n + number_length..];
+        let unit = DURATION_UNITS
+            .iter()
+            .map(|unit| unit.chars().collect::<Vec<_>>())
+            .filter(|unit| rest.starts_with(unit))
+            .map(|unit| unit.len())
+            .max();
+        let unit = match unit {
+            Some(unit) => unit,
+            None => break,
+        };
+        n += number_length + unit;
+    }
+    if n > 0 {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn progress_messages_share_template() {
+        let a = template(
+            "[ 389/9733  3% ETA=2022-04-01 20:50:07.341561] Ingesting 389 wells. 659.39 items/sec.",
+        );
+        let b = template(
+            "[1167/9733 11% ETA=2022-04-01 20:50:06.868555] Ingesting 389 wells. 693.10 items/sec.",
+        );
+        assert_eq!(a, b);
+        assert_eq!(
+            a,
+            "[<num>/<num> <num>% ETA=<num>-<num>-<num> <num>:<num>:<num>] Ingesting <num> wells. <num> items/sec."
+        );
+    }
+
+    #[test]
+    fn masks_ids_strings_and_durations() {
+        assert_eq!(
+            template("Request 776f2d01-8bba-4c36-b6a8-5f7074c096d7 for 'wells' took 150ms"),
+            "Request <uuid> for <str> took <duration>"
+        );
+        assert_eq!(
+            template("Retrying \"upload\" in 1m30s at 0x7ffd3a2b, commit 3fa85f64ab"),
+            "Retrying <str> in <duration> at <hex>, commit <hex>"
+        );
+        assert_eq!(
+            template("Don't touch v2 or user42"),
+            "Don't touch v2 or user42"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use std::env;
+use std::sync::OnceLock;
+
+use clap::ValueEnum as ClapValueEnum;
+use eyre::{eyre, Context};
+
+use crate::color::{
+    Ink, Rgb, Style, BLUE, BRIGHT_BLACK, BRIGHT_CYAN, BRIGHT_GREEN, BRIGHT_MAGENTA, BRIGHT_RED,
+    BRIGHT_WHITE, BRIGHT_YELLOW, CYAN, GREEN, MAGENTA, RED, WHITE, YELLOW,
+};
+
+#[derive(ClapValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ThemeName {
+    Dark,
+    Light,
+    HighContrast,
+    /// Okabe-Ito colors, which stay apart with the common kinds of color
+    /// blindness.
+    Colorblind,
+}
+
+const SKY_BLUE: Ink = Ink::Rgb(Rgb(86, 180, 233));
+const BLUISH_GREEN: Ink = Ink::Rgb(Rgb(0, 158, 115));
+const OKABE_YELLOW: Ink = Ink::Rgb(Rgb(240, 228, 66));
+const VERMILLION: Ink = Ink::Rgb(Rgb(213, 94, 0));
+const REDDISH_PURPLE: Ink = Ink::Rgb(Rgb(204, 121, 167));
+const DARK_ORANGE: Ink = Ink::Rgb(Rgb(175, 95, 0));
+
+/// The styles of each element of the output. Severity styles are indexed by
+/// `Severity as usize`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub time: Style,
+    /// Process ids. Request ids get colors from their hash.
+    pub id: Style,
+    /// The `--extra` values, in turn.
+    pub extras: [Style; 3],
+    /// Keys in the verbose output and in span fields.
+    pub key: Style,
+    /// Secondary details, like duplicate counts and examples.
+    pub dim: Style,
+    pub span: Style,
+    /// Syntax colors of nested values in the verbose output.
+    pub string: Style,
+    pub number: Style,
+    /// `true`, `false` and `null`.
+    pub literal: Style,
+    pub severities: [Style; 6],
+    pub messages: [Style; 6],
+}
+
+impl Theme {
+    pub fn new(name: ThemeName) -> Self {
+        let bold = Style {
+            fg: None,
+            bold: true,
+        };
+        match name {
+            ThemeName::Dark => Theme {
+                time: Style::fg(GREEN),
+                id: bold,
+                extras: [
+                    Style::fg(BRIGHT_BLACK),
+                    Style::fg(BRIGHT_CYAN),
+                    Style::fg(BRIGHT_MAGENTA),
+                ],
+                key: Style::fg(BRIGHT_BLACK),
+                dim: Style::fg(BRIGHT_BLACK),
+                span: Style::bold(CYAN),
+                string: Style::fg(GREEN),
+                number: Style::fg(CYAN),
+                literal: Style::fg(YELLOW),
+                severities: [
+                    Style::bold(BRIGHT_BLACK),
+                    Style::bold(BRIGHT_BLACK),
+                    Style::bold(BRIGHT_BLACK),
+                    Style::bold(YELLOW),
+                    Style::bold(RED),
+                    Style::bold(MAGENTA),
+                ],
+                messages: [
+                    Style::fg(BRIGHT_BLACK),
+                    Style::fg(BRIGHT_BLACK),
+                    Style::fg(WHITE),
+                    Style::fg(YELLOW),
+                    Style::fg(RED),
+                    Style::fg(MAGENTA),
+                ],
+            },
+            ThemeName::Light => Theme {
+                time: Style::fg(GREEN),
+                id: bold,
+                extras: [Style::fg(BRIGHT_BLACK), Style::fg(BLUE), Style::fg(MAGENTA)],
+                key: Style::fg(BRIGHT_BLACK),
+                dim: Style::fg(BRIGHT_BLACK),
+                span: Style::bold(BLUE),
+                string: Style::fg(GREEN),
+                number: Style::fg(BLUE),
+                literal: Style::fg(MAGENTA),
+                severities: [
+                    Style::bold(BRIGHT_BLACK),
+                    Style::bold(BRIGHT_BLACK),
+                    Style::bold(BLUE),
+                    Style::bold(DARK_ORANGE),
+                    Style::bold(RED),
+                    Style::bold(MAGENTA),
+                ],
+                messages: [
+                    Style::fg(BRIGHT_BLACK),
+                    Style::fg(BRIGHT_BLACK),
+                    Style::default(),
+                    Style::fg(DARK_ORANGE),
+                    Style::fg(RED),
+                    Style::fg(MAGENTA),
+                ],
+            },
+            ThemeName::HighContrast => Theme {
+                time: Style::bold(BRIGHT_GREEN),
+                id: bold,
+                extras: [
+                    Style::fg(BRIGHT_CYAN),
+                    Style::fg(BRIGHT_MAGENTA),
+                    Style::fg(BRIGHT_YELLOW),
+                ],
+                key: Style::fg(BRIGHT_CYAN),
+                dim: Style::fg(WHITE),
+                span: Style::bold(BRIGHT_CYAN),
+                string: Style::fg(BRIGHT_GREEN),
+                number: Style::fg(BRIGHT_CYAN),
+                literal: Style::fg(BRIGHT_YELLOW),
+                severities: [
+                    Style::bold(WHITE),
+                    Style::bold(WHITE),
+                    Style::bold(BRIGHT_WHITE),
+                    Style::bold(BRIGHT_YELLOW),
+                    Style::bold(BRIGHT_RED),
+                    Style::bold(BRIGHT_MAGENTA),
+                ],
+                messages: [
+                    Style::fg(WHITE),
+                    Style::fg(WHITE),
+                    Style::fg(BRIGHT_WHITE),
+                    Style::bold(BRIGHT_YELLOW),
+                    Style::bold(BRIGHT_RED),
+                    Style::bold(BRIGHT_MAGENTA),
+                ],
+            },
+            ThemeName::Colorblind => Theme {
+                time: Style::fg(BLUISH_GREEN),
+                id: bold,
+                extras: [
+                    Style::fg(BRIGHT_BLACK),
+                    Style::fg(SKY_BLUE),
+                    Style::fg(REDDISH_PURPLE),
+                ],
+                key: Style::fg(BRIGHT_BLACK),
+                dim: Style::fg(BRIGHT_BLACK),
+                span: Style::bold(SKY_BLUE),
+                string: Style::fg(BLUISH_GREEN),
+                number: Style::fg(SKY_BLUE),
+                literal: Style::fg(OKABE_YELLOW),
+                severities: [
+                    Style::bold(BRIGHT_BLACK),
+                    Style::bold(BRIGHT_BLACK),
+                    Style::bold(SKY_BLUE),
+                    Style::bold(OKABE_YELLOW),
+                    Style::bold(VERMILLION),
+                    Style::bold(REDDISH_PURPLE),
+                ],
+                messages: [
+                    Style::fg(BRIGHT_BLACK),
+                    Style::fg(BRIGHT_BLACK),
+                    Style::fg(WHITE),
+                    Style::fg(OKABE_YELLOW),
+                    Style::fg(VERMILLION),
+                    Style::fg(REDDISH_PURPLE),
+                ],
+            },
+        }
+    }
+
+    /// Applies overrides like `time=blue:error=bold #ff0000:key=default`.
+    /// Severities are set with their name, and their messages with eg.
+    /// `error-message`.
+    fn apply(&mut self, overrides: &str) -> eyre::Result<()> {
+        const SEVERITIES: [&str; 6] = ["trace", "debug", "info", "warning", "error", "fatal"];
+        for assignment in overrides.split(':').filter(|a| !a.trim().is_empty()) {
+            let (element, style) = assignment
+                .split_once('=')
+                .ok_or_else(|| eyre!("Expected element=style in `{}`", assignment))?;
+            let element = element.trim();
+            let style: Style = style
+                .parse()
+                .wrap_err_with(|| format!("Invalid style for `{}`", element))?;
+            let severity = |name: &str| SEVERITIES.iter().position(|s| *s == name);
+            match element {
+                "time" => self.time = style,
+                "id" => self.id = style,
+                "extra" => self.extras = [style; 3],
+                "extra1" | "extra2" | "extra3" => {
+                    let i = element[5..].parse::<usize>()? - 1;
+                    self.extras[i] = style;
+                }
+                "key" => self.key = style,
+                "dim" => self.dim = style,
+                "span" => self.span = style,
+                "string" => self.string = style,
+                "number" => self.number = style,
+                "literal" => self.literal = style,
+                _ => match element.strip_suffix("-message").map(severity) {
+                    Some(Some(i)) => self.messages[i] = style,
+                    Some(None) => return Err(eyre!("Unknown element `{}`", element)),
+                    None => match severity(element) {
+                        Some(i) => self.severities[i] = style,
+                        None => return Err(eyre!("Unknown element `{}`", element)),
+                    },
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+
+/// The theme set by [init], or the dark theme.
+pub fn theme() -> &'static Theme {
+    THEME.get_or_init(|| Theme::new(ThemeName::Dark))
+}
+
+/// Sets the theme for the rest of the run: the given one, or `CLOG_THEME`,
+/// with the overrides from `CLOG_COLORS` applied.
+pub fn init(name: Option<ThemeName>) -> eyre::Result<()> {
+    let name = match (name, env::var("CLOG_THEME")) {
+        (Some(name), _) => name,
+        (None, Ok(name)) => {
+            ThemeName::from_str(&name, true).map_err(|e| eyre!("Invalid CLOG_THEME: {}", e))?
+        }
+        (None, Err(_)) => ThemeName::Dark,
+    };
+    let mut theme = Theme::new(name);
+    theme
+        .apply(&env::var("CLOG_COLORS").unwrap_or_default())
+        .wrap_err("Invalid CLOG_COLORS")?;
+    let _ = THEME.set(theme);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn applies_overrides() {
+        let mut theme = Theme::new(ThemeName::Dark);
+        theme
+            .apply("time=blue: error=bold #ff0000:info-message=default:extra2=cyan")
+            .unwrap();
+        let mut expected = Theme::new(ThemeName::Dark);
+        expected.time = Style::fg(BLUE);
+        expected.severities[4] = Style::bold(Ink::Rgb(Rgb(255, 0, 0)));
+        expected.messages[2] = Style::default();
+        expected.extras[1] = Style::fg(CYAN);
+        assert_eq!(expected, theme);
+
+        assert!(theme.apply("time").is_err());
+        assert!(theme.apply("timestamp=red").is_err());
+        assert!(theme.apply("warn-message=red").is_err());
+        assert!(theme.apply("time=purple").is_err());
+    }
+}
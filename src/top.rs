@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+
+use crate::template::template;
+use crate::theme::theme;
+use crate::{oneline, Parser, ParserOutput, PrintConfig, Report, Severity};
+
+const MAX_EXAMPLE_LENGTH: usize = 120;
+
+/// `message` on one line of at most `max_length` characters, with control
+/// characters like tabs escaped so that they can't break the columns.
+fn one_row(message: &str, max_length: usize) -> String {
+    let escaped: String = message
+        .chars()
+        .map(|c| match c {
+            '\n' => c.to_string(),
+            c if c.is_control() => c.escape_default().to_string(),
+            c => c.to_string(),
+        })
+        .collect();
+    oneline(&escaped, max_length)
+}
+
+struct Template {
+    count: usize,
+    first: DateTime<Utc>,
+    last: DateTime<Utc>,
+    example: String,
+}
+
+/// The most common message templates, for `clog top`.
+pub struct TopMessages {
+    limit: usize,
+    templates: HashMap<(String, Severity), Template>,
+}
+
+impl TopMessages {
+    pub fn new(limit: usize) -> Self {
+        TopMessages {
+            limit,
+            templates: HashMap::new(),
+        }
+    }
+}
+
+impl Report for TopMessages {
+    fn add(&mut self, output: &ParserOutput) {
+        let line = match output {
+            ParserOutput::Log(line) => line,
+            _ => return,
+        };
+        let message = line.raw_message().map(|m| m.1).unwrap_or_default();
+        let key = (template(&message), line.severity());
+        let entry = self.templates.entry(key).or_insert_with(|| Template {
+            count: 0,
+            first: line.time,
+            last: line.time,
+            example: one_row(&message, MAX_EXAMPLE_LENGTH),
+        });
+        entry.count += 1;
+        entry.first = entry.first.min(line.time);
+        entry.last = entry.last.max(line.time);
+    }
+
+    fn print(
+        &self,
+        f: &mut dyn Write,
+        config: &PrintConfig,
+        _parser: &Parser,
+    ) -> std::io::Result<()> {
+        let mut sorted: Vec<_> = self.templates.iter().collect();
+        sorted.sort_by(|a, b| {
+            b.1.count
+                .cmp(&a.1.count)
+                .then(b.0 .1.cmp(&a.0 .1))
+                .then(a.0 .0.cmp(&b.0 .0))
+        });
+
+        let count_width = sorted
+            .first()
+            .map(|(_, t)| t.count.to_string().len())
+            .unwrap_or(0)
+            .max("Count".len());
+        let time_width = config.format_time(&Utc::now()).len();
+        writeln!(
+            f,
+            "{}",
+            format!(
+                "{:>count_width$}  {:8}  {:time_width$}  {:time_width$}  Template",
+                "Count", "Severity", "First seen", "Last seen"
+            )
+            .bold()
+        )?;
+        for ((template, severity), t) in sorted.into_iter().take(self.limit) {
+            let theme = theme();
+            let (severity_style, message_style) = severity.colors();
+            let name = format!("{:8}", format!("{severity:?}").to_uppercase());
+            writeln!(
+                f,
+                "{:>count_width$}  {}  {}  {}  {}",
+                t.count,
+                severity_style.paint(name),
+                theme.time.paint(config.format_time(&t.first)),
+                theme.time.paint(config.format_time(&t.last)),
+                message_style.paint(one_row(template, MAX_EXAMPLE_LENGTH)),
+            )?;
+            let indent = count_width + 2 + 8 + 2 + 2 * (time_width + 2);
+            writeln!(
+                f,
+                "{}{}",
+                " ".repeat(indent),
+                theme.dim.paint(format!("e.g. {}", t.example))
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::print_report;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn collapses_progress_messages() {
+        colored::control::set_override(false);
+        let input = r#"{"message": "[ 389/9733  3%] Ingesting 389 wells. 659.39 items/sec.", "timestamp": "2022-04-01T18:49:52.925Z", "severity": "INFO"}
+{"message": "Connection lost", "timestamp": "2022-04-01T18:49:53Z", "severity": "ERROR"}
+{"message": "[1167/9733 11%] Ingesting 389 wells. 693.10 items/sec.", "timestamp": "2022-04-01T18:49:54Z", "severity": "INFO"}
+{"message": "[1556/9733 15%] Ingesting 389 wells. 695.18 items/sec.", "timestamp": "2022-04-01T18:49:55Z", "severity": "INFO"}
+{"message": "Connection lost", "timestamp": "2022-04-01T18:49:56Z", "severity": "ERROR"}
+{"message": "[1945/9733 19%] Ingesting 389 wells. 659.43 items/sec.", "timestamp": "2022-04-01T18:49:57Z", "severity": "WARNING"}"#;
+        let mut top = TopMessages::new(2);
+        let printed = print_report(&mut top, input, &PrintConfig::default());
+        let expected = r#"Count  Severity  First seen                Last seen                 Template
+    3  INFO      2022-04-01 18:49:52.925Z  2022-04-01 18:49:55.000Z  [<num>/<num> <num>%] Ingesting <num> wells. <num> items/sec.
+                                                                     e.g. [ 389/9733  3%] Ingesting 389 wells. 659.39 items/sec.
+    2  ERROR     2022-04-01 18:49:53.000Z  2022-04-01 18:49:56.000Z  Connection lost
+                                                                     e.g. Connection lost
+"#;
+        assert_eq!(expected, printed);
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        colored::control::set_override(false);
+        let input = r#"{"message": "Failed:\tno\u001b[1m route\r\nat main", "timestamp": "2022-04-01T18:49:52Z", "severity": "ERROR"}"#;
+        let printed = print_report(&mut TopMessages::new(1), input, &PrintConfig::default());
+        let expected = r#"Count  Severity  First seen                Last seen                 Template
+    1  ERROR     2022-04-01 18:49:52.000Z  2022-04-01 18:49:52.000Z  Failed: no\u{1b}[<duration> route at main
+                                                                     e.g. Failed:\tno\u{1b}[1m route\r ⤶ at main
+"#;
+        assert_eq!(expected, printed);
+    }
+}
@@ -0,0 +1,521 @@
+use std::io::{self, IsTerminal, Write};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::{cursor, execute, queue, terminal};
+use eyre::bail;
+
+use crate::filter::FieldFilter;
+use crate::{Parser, ParserOutput, PrintConfig, Severity};
+
+/// The maximum number of blocks of input lines read between two redraws.
+const BLOCKS_PER_FRAME: usize = 16;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Input {
+    Text,
+    Fields,
+}
+
+/// The state of the interactive viewer, separate from the terminal so that
+/// it can be tested.
+struct Viewer {
+    config: PrintConfig,
+    entries: Vec<ParserOutput>,
+    /// Indices of the entries that pass the filters.
+    visible: Vec<usize>,
+    /// Index into `visible` of the selected entry.
+    selected: usize,
+    /// Index into `visible` of the first entry on screen.
+    top: usize,
+    expanded: bool,
+    follow: bool,
+    eof: bool,
+    min_severity: Option<Severity>,
+    text: String,
+    fields: String,
+    field_filter: FieldFilter,
+    field_error: Option<String>,
+    /// The filter being edited, and its value before editing.
+    input: Option<(Input, String)>,
+}
+
+impl Viewer {
+    fn new(config: PrintConfig, min_severity: Option<Severity>) -> Self {
+        Viewer {
+            config,
+            entries: vec![],
+            visible: vec![],
+            selected: 0,
+            top: 0,
+            expanded: false,
+            follow: true,
+            eof: false,
+            min_severity,
+            text: String::new(),
+            fields: String::new(),
+            field_filter: FieldFilter::default(),
+            field_error: None,
+            input: None,
+        }
+    }
+
+    fn push(&mut self, output: ParserOutput) {
+        if let ParserOutput::None = output {
+            return;
+        }
+        if self.matches(&output) {
+            self.visible.push(self.entries.len());
+            if self.follow {
+                self.selected = self.visible.len() - 1;
+            }
+        }
+        self.entries.push(output);
+    }
+
+    fn matches(&self, output: &ParserOutput) -> bool {
+        let text = self.text.to_lowercase();
+        match output {
+            ParserOutput::None => false,
+            ParserOutput::Text(s) => s.to_lowercase().contains(&text),
+            ParserOutput::Log(line) => {
+                let message = line.raw_message().map(|m| m.1).unwrap_or_default();
+                self.min_severity
+                    .as_ref()
+                    .is_none_or(|minimum| line.severity() >= *minimum)
+                    && message.to_lowercase().contains(&text)
+                    && self.field_filter.matches(line)
+            }
+        }
+    }
+
+    /// Applies changed filters, keeping the selection on the same entry or
+    /// the closest one before it.
+    fn refilter(&mut self) {
+        let selected_entry = self.visible.get(self.selected).copied().unwrap_or(0);
+        self.visible = (0..self.entries.len())
+            .filter(|i| self.matches(&self.entries[*i]))
+            .collect();
+        self.selected = if self.follow {
+            self.visible.len().saturating_sub(1)
+        } else {
+            self.visible
+                .iter()
+                .rposition(|i| *i <= selected_entry)
+                .unwrap_or(0)
+        };
+        self.top = self.top.min(self.selected);
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        let last = self.visible.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + delta).clamp(0, last.max(0)) as usize;
+        self.follow = false;
+    }
+
+    /// Selects the next (or previous) entry with severity error or worse.
+    fn jump_to_error(&mut self, forward: bool) {
+        let is_error = |i: &usize| match &self.entries[self.visible[*i]] {
+            ParserOutput::Log(line) => line.severity() >= Severity::Error,
+            _ => false,
+        };
+        let found = if forward {
+            (self.selected + 1..self.visible.len()).find(is_error)
+        } else {
+            (0..self.selected).rev().find(is_error)
+        };
+        if let Some(i) = found {
+            self.selected = i;
+            self.follow = false;
+        }
+    }
+
+    fn cycle_severity(&mut self) {
+        self.min_severity = match &self.min_severity {
+            None => Some(Severity::Debug),
+            Some(Severity::Fatal) => None,
+            Some(current) => Severity::ALL.iter().find(|s| *s > current).cloned(),
+        };
+        self.refilter();
+    }
+
+    fn set_fields(&mut self, fields: String) {
+        match fields.parse() {
+            Ok(filter) => {
+                self.field_filter = filter;
+                self.field_error = None;
+            }
+            Err(e) => self.field_error = Some(e.to_string()),
+        }
+        self.fields = fields;
+        self.refilter();
+    }
+
+    /// Returns true when the viewer should quit.
+    fn handle_key(&mut self, key: KeyEvent, page: usize) -> bool {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return true;
+        }
+        if let Some((input, previous)) = self.input.clone() {
+            let mut value = match input {
+                Input::Text => self.text.clone(),
+                Input::Fields => self.fields.clone(),
+            };
+            match key.code {
+                KeyCode::Enter => self.input = None,
+                KeyCode::Esc => {
+                    self.input = None;
+                    value = previous;
+                }
+                KeyCode::Backspace => {
+                    value.pop();
+                }
+                KeyCode::Char(c) => value.push(c),
+                _ => return false,
+            }
+            match input {
+                Input::Text => {
+                    self.text = value;
+                    self.refilter();
+                }
+                Input::Fields => self.set_fields(value),
+            }
+            return false;
+        }
+
+        let page = page.max(1) as isize;
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Down | KeyCode::Char('j') => self.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_by(-1),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.move_by(page),
+            KeyCode::PageUp | KeyCode::Char('b') => self.move_by(-page),
+            KeyCode::Home | KeyCode::Char('g') => self.move_by(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_by(isize::MAX / 2),
+            KeyCode::Enter => self.expanded = !self.expanded,
+            KeyCode::Char('n') => self.jump_to_error(true),
+            KeyCode::Char('N') => self.jump_to_error(false),
+            KeyCode::Char('s') => self.cycle_severity(),
+            KeyCode::Char('F') => {
+                self.follow = !self.follow;
+                if self.follow {
+                    self.selected = self.visible.len().saturating_sub(1);
+                }
+            }
+            KeyCode::Char('/') => self.input = Some((Input::Text, self.text.clone())),
+            KeyCode::Char('f') => self.input = Some((Input::Fields, self.fields.clone())),
+            _ => {}
+        }
+        false
+    }
+
+    fn render_entry(&self, index: usize, expanded: bool, width: usize) -> Vec<String> {
+        let config = PrintConfig {
+            verbose: expanded,
+            oneline: !expanded,
+            max_width: Some(width),
+            ..self.config.clone()
+        };
+        let mut buffer = Vec::new();
+        self.entries[index]
+            .print(&mut buffer, &config)
+            .unwrap_or_default();
+        let rendered = String::from_utf8_lossy(&buffer);
+        let mut lines: Vec<_> = rendered.lines().map(|l| l.replace('\t', "    ")).collect();
+        if !expanded {
+            lines.truncate(1);
+        }
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        lines
+    }
+
+    /// Scrolls so that all of the selected entry is on screen.
+    fn scroll(&mut self, height: usize, width: usize) {
+        if self.selected < self.top {
+            self.top = self.selected;
+        }
+        let selected_height = match self.visible.get(self.selected) {
+            Some(index) => self.render_entry(*index, self.expanded, width).len(),
+            None => 1,
+        }
+        .min(height);
+        while self.selected - self.top + selected_height > height {
+            self.top += 1;
+        }
+    }
+
+    fn status(&self) -> String {
+        if let Some((input, _)) = &self.input {
+            return match input {
+                Input::Text => format!("/{}", self.text),
+                Input::Fields => match &self.field_error {
+                    Some(e) => format!("fields: {}  ({e})", self.fields),
+                    None => format!("fields: {}", self.fields),
+                },
+            };
+        }
+        let mut parts = vec![format!("{}/{}", self.visible.len(), self.entries.len())];
+        if let Some(minimum) = &self.min_severity {
+            parts.push(format!(">= {}", format!("{minimum:?}").to_uppercase()));
+        }
+        if !self.text.is_empty() {
+            parts.push(format!("/{}", self.text));
+        }
+        if !self.fields.is_empty() {
+            parts.push(format!("fields: {}", self.fields));
+        }
+        if self.follow {
+            parts.push("FOLLOW".to_string());
+        }
+        if self.eof {
+            parts.push("EOF".to_string());
+        }
+        parts.push(
+            "q quit  / text  f fields  s severity  n/N error  Enter expand  F follow".to_string(),
+        );
+        parts.join(" | ")
+    }
+
+    fn render<W>(&mut self, f: &mut W, width: usize, height: usize) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let list_height = height.saturating_sub(1);
+        let entry_width = width.saturating_sub(2);
+        self.scroll(list_height, entry_width);
+
+        let mut row = 0;
+        let mut i = self.top;
+        while row < list_height && i < self.visible.len() {
+            let is_selected = i == self.selected;
+            let lines =
+                self.render_entry(self.visible[i], is_selected && self.expanded, entry_width);
+            for (j, line) in lines.iter().enumerate() {
+                if row >= list_height {
+                    break;
+                }
+                let marker = if is_selected && j == 0 { "> " } else { "  " };
+                queue!(
+                    f,
+                    cursor::MoveTo(0, row as u16),
+                    terminal::Clear(terminal::ClearType::CurrentLine),
+                    SetAttribute(Attribute::Bold),
+                    Print(marker),
+                    SetAttribute(Attribute::Reset),
+                    Print(line),
+                    SetAttribute(Attribute::Reset),
+                )?;
+                row += 1;
+            }
+            i += 1;
+        }
+        while row < list_height {
+            queue!(
+                f,
+                cursor::MoveTo(0, row as u16),
+                terminal::Clear(terminal::ClearType::CurrentLine)
+            )?;
+            row += 1;
+        }
+
+        let status: String = self.status().chars().take(width).collect();
+        queue!(
+            f,
+            cursor::MoveTo(0, list_height as u16),
+            terminal::Clear(terminal::ClearType::CurrentLine),
+            SetAttribute(Attribute::Reverse),
+            Print(format!("{status:width$}")),
+            SetAttribute(Attribute::Reset),
+        )?;
+        f.flush()
+    }
+}
+
+/// Restores the terminal when the viewer exits, also on errors and panics.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(
+            io::stdout(),
+            terminal::EnterAlternateScreen,
+            terminal::DisableLineWrap,
+            cursor::Hide
+        )?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(
+            io::stdout(),
+            cursor::Show,
+            terminal::EnableLineWrap,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Runs the full-screen viewer over the lines read from stdin. Keys are read
+/// from the terminal, so that the logs can still be piped in.
+pub fn run(
+    config: PrintConfig,
+    min_severity: Option<Severity>,
+    mut parser: Parser,
+    blocks: Receiver<io::Result<Vec<u8>>>,
+) -> eyre::Result<()> {
+    if !io::stdout().is_terminal() {
+        bail!("clog tui needs a terminal to draw on");
+    }
+    let mut viewer = Viewer::new(config, min_severity);
+    let _terminal = RawTerminal::enter()?;
+    let mut stdout = io::stdout();
+    let mut dirty = true;
+
+    loop {
+        for _ in 0..BLOCKS_PER_FRAME {
+            match blocks.try_recv() {
+                Ok(Ok(block)) => {
+                    for output in parser.push_bytes(block) {
+                        viewer.push(output);
+                    }
+                    dirty = true;
+                }
+                Ok(Err(e)) => return Err(e.into()),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if !viewer.eof {
+                        viewer.push(parser.flush());
+                        viewer.eof = true;
+                        dirty = true;
+                    }
+                    break;
+                }
+            }
+        }
+
+        let (width, height) = terminal::size()?;
+        if dirty {
+            viewer.render(&mut stdout, width as usize, height as usize)?;
+            dirty = false;
+        }
+
+        if event::poll(Duration::from_millis(50))? {
+            match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    let page = (height as usize).saturating_sub(2);
+                    if viewer.handle_key(key, page) {
+                        break;
+                    }
+                    dirty = true;
+                }
+                Event::Resize(_, _) => dirty = true,
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn viewer() -> Viewer {
+        colored::control::set_override(false);
+        let mut viewer = Viewer::new(PrintConfig::default(), None);
+        let input = r#"{"message": "starting", "timestamp": "2022-04-01T18:49:52Z", "severity": "INFO"}
+{"message": "failed to connect", "timestamp": "2022-04-01T18:49:53Z", "severity": "ERROR", "status": 503}
+Some text
+{"message": "retrying", "timestamp": "2022-04-01T18:49:54Z", "severity": "DEBUG", "status": 200}
+{"message": "failed again", "timestamp": "2022-04-01T18:49:55Z", "severity": "FATAL"}"#;
+        for output in Parser::new().push_lines(input) {
+            viewer.push(output);
+        }
+        viewer
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn follows_and_jumps_between_errors() {
+        let mut viewer = viewer();
+        assert_eq!(4, viewer.selected);
+
+        viewer.handle_key(key(KeyCode::Char('g')), 10);
+        assert_eq!((0, false), (viewer.selected, viewer.follow));
+        viewer.handle_key(key(KeyCode::Char('n')), 10);
+        assert_eq!(1, viewer.selected);
+        viewer.handle_key(key(KeyCode::Char('n')), 10);
+        assert_eq!(4, viewer.selected);
+        viewer.handle_key(key(KeyCode::Char('N')), 10);
+        assert_eq!(1, viewer.selected);
+    }
+
+    #[test]
+    fn edits_filters() {
+        let mut viewer = viewer();
+        viewer.handle_key(key(KeyCode::Char('/')), 10);
+        for c in "fail".chars() {
+            viewer.handle_key(key(KeyCode::Char(c)), 10);
+        }
+        viewer.handle_key(key(KeyCode::Enter), 10);
+        assert_eq!(vec![1, 4], viewer.visible);
+
+        viewer.handle_key(key(KeyCode::Char('f')), 10);
+        for c in "status>500".chars() {
+            viewer.handle_key(key(KeyCode::Char(c)), 10);
+        }
+        viewer.handle_key(key(KeyCode::Esc), 10);
+        assert_eq!("", viewer.fields);
+        assert_eq!(vec![1, 4], viewer.visible);
+
+        viewer.handle_key(key(KeyCode::Char('s')), 10);
+        assert_eq!(Some(Severity::Debug), viewer.min_severity);
+        viewer.handle_key(key(KeyCode::Char('/')), 10);
+        viewer.handle_key(key(KeyCode::Esc), 10);
+        viewer.handle_key(key(KeyCode::Char('/')), 10);
+        for _ in 0..4 {
+            viewer.handle_key(key(KeyCode::Backspace), 10);
+        }
+        viewer.handle_key(key(KeyCode::Enter), 10);
+        assert_eq!(vec![0, 1, 2, 3, 4], viewer.visible);
+    }
+
+    #[test]
+    fn expands_selected_entry() {
+        let mut viewer = viewer();
+        viewer.handle_key(key(KeyCode::Char('g')), 10);
+        viewer.handle_key(key(KeyCode::Char('j')), 10);
+        viewer.handle_key(key(KeyCode::Enter), 10);
+        let lines = viewer.render_entry(viewer.visible[viewer.selected], viewer.expanded, 80);
+        assert_eq!(
+            vec![
+                "2022-04-01 18:49:53.000Z ERROR   failed to connect",
+                "  status = 503"
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn renders_on_tiny_terminals() {
+        let mut viewer = viewer();
+        viewer.handle_key(key(KeyCode::Enter), 10);
+        let mut empty = Viewer::new(viewer.config.clone(), None);
+        for (width, height) in [(0, 0), (1, 0), (0, 1), (1, 1), (2, 2)] {
+            viewer.render(&mut Vec::new(), width, height).unwrap();
+            empty.render(&mut Vec::new(), width, height).unwrap();
+        }
+    }
+}